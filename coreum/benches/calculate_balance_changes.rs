@@ -0,0 +1,66 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use coreum::{calculate_balance_changes, Balance, Coin, DenomDefinition, MultiSend, Rate};
+
+// `accounts` senders each send every one of `denoms` denoms to their own
+// recipient, so the transaction holds `accounts * denoms` coins on each side.
+fn transfer(accounts: usize, denoms: usize, burn_rate: Rate, commission_rate: Rate) -> (Vec<Balance>, Vec<DenomDefinition>, MultiSend) {
+    let denom = |d: usize| format!("denom{}", d);
+    let definitions = (0..denoms).map(|d| DenomDefinition::new(denom(d), "issuer_account", burn_rate, commission_rate)).collect();
+    let original_balances = (0..accounts)
+        .map(|a| Balance::new(format!("account{}", a), (0..denoms).map(|d| Coin::new(denom(d), 1_000_000_000)).collect()))
+        .collect();
+    let inputs: Vec<Balance> = (0..accounts)
+        .map(|a| Balance::new(format!("account{}", a), (0..denoms).map(|d| Coin::new(denom(d), 1_000 + a as i128)).collect()))
+        .collect();
+    let outputs = inputs
+        .iter()
+        .enumerate()
+        .map(|(a, input)| Balance::new(format!("recipient{}", a), input.coins().to_vec()))
+        .collect();
+    (original_balances, definitions, MultiSend::new(inputs, outputs))
+}
+
+fn bench_calculate_balance_changes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("calculate_balance_changes");
+    // 10_000x1 has the most addresses, so it leans hardest on balance map growth.
+    for &(accounts, denoms) in &[(10, 1), (100, 10), (1_000, 10), (100, 100), (10_000, 1)] {
+        let parameter = format!("{}x{}", accounts, denoms);
+        group.bench_with_input(BenchmarkId::new("zero_rate", &parameter), &(accounts, denoms), |b, &(accounts, denoms)| {
+            let (original_balances, definitions, multi_send_tx) = transfer(accounts, denoms, Rate::ZERO, Rate::ZERO);
+            b.iter(|| calculate_balance_changes(&original_balances, &definitions, &multi_send_tx))
+        });
+        // Odd rates leave remainders on every share, exercising the full split.
+        group.bench_with_input(BenchmarkId::new("with_fees", &parameter), &(accounts, denoms), |b, &(accounts, denoms)| {
+            let (original_balances, definitions, multi_send_tx) = transfer(accounts, denoms, Rate::from_bps(1_337), Rate::from_bps(777));
+            b.iter(|| calculate_balance_changes(&original_balances, &definitions, &multi_send_tx))
+        });
+    }
+    group.finish();
+}
+
+#[cfg(feature = "rayon")]
+fn bench_calculate_balance_changes_parallel(c: &mut Criterion) {
+    let mut group = c.benchmark_group("calculate_balance_changes_parallel");
+    // Only the denoms are split across tasks, so the single denom sizes show
+    // the overhead. Doubling them should roughly double both timings; the
+    // prep that builds the parts is one pass over the transaction.
+    for &(accounts, denoms) in &[(100, 10), (100, 100), (10, 1_000), (10_000, 1), (20_000, 1), (40_000, 1)] {
+        let parameter = format!("{}x{}", accounts, denoms);
+        group.bench_with_input(BenchmarkId::new("serial", &parameter), &(accounts, denoms), |b, &(accounts, denoms)| {
+            let (original_balances, definitions, multi_send_tx) = transfer(accounts, denoms, Rate::from_bps(1_337), Rate::from_bps(777));
+            b.iter(|| calculate_balance_changes(&original_balances, &definitions, &multi_send_tx))
+        });
+        group.bench_with_input(BenchmarkId::new("parallel", &parameter), &(accounts, denoms), |b, &(accounts, denoms)| {
+            let (original_balances, definitions, multi_send_tx) = transfer(accounts, denoms, Rate::from_bps(1_337), Rate::from_bps(777));
+            b.iter(|| coreum::calculate_balance_changes_parallel(&original_balances, &definitions, &multi_send_tx))
+        });
+    }
+    group.finish();
+}
+
+#[cfg(not(feature = "rayon"))]
+criterion_group!(benches, bench_calculate_balance_changes);
+#[cfg(feature = "rayon")]
+criterion_group!(benches, bench_calculate_balance_changes, bench_calculate_balance_changes_parallel);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde_json::Value;
+
+// Any input, however malformed, must come back as JSON: the balance changes or
+// an `{"error": "..."}` object, never a panic.
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+    let output = coreum::calculate_balance_changes_json(input);
+    match serde_json::from_str::<Value>(&output) {
+        Ok(Value::Array(_)) => {}
+        Ok(Value::Object(object)) => assert!(object.get("error").is_some_and(Value::is_string), "{}", output),
+        _ => panic!("not a result or an error: {}", output),
+    }
+});
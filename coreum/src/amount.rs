@@ -0,0 +1,129 @@
+use core::fmt;
+use core::ops::{Add, Sub};
+use core::str::FromStr;
+
+#[cfg(not(feature = "std"))]
+use crate::compat::*;
+use crate::BalanceError;
+
+/// A coin amount in the denom's smallest unit. Kept apart from plain integers
+/// so it can't be mixed up with a rate or a count, and `+`/`-` are checked:
+/// they return [`AmountOverflow`] instead of wrapping or panicking.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(i128);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    pub const fn new(amount: i128) -> Self {
+        Amount(amount)
+    }
+
+    pub const fn get(self) -> i128 {
+        self.0
+    }
+}
+
+impl From<i128> for Amount {
+    fn from(amount: i128) -> Self {
+        Amount(amount)
+    }
+}
+
+impl From<Amount> for i128 {
+    fn from(amount: Amount) -> Self {
+        amount.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmountOverflow;
+
+impl fmt::Display for AmountOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "amount overflow")
+    }
+}
+
+impl core::error::Error for AmountOverflow {}
+
+impl Add for Amount {
+    type Output = Result<Amount, AmountOverflow>;
+
+    fn add(self, rhs: Amount) -> Self::Output {
+        self.0.checked_add(rhs.0).map(Amount).ok_or(AmountOverflow)
+    }
+}
+
+impl Sub for Amount {
+    type Output = Result<Amount, AmountOverflow>;
+
+    fn sub(self, rhs: Amount) -> Self::Output {
+        self.0.checked_sub(rhs.0).map(Amount).ok_or(AmountOverflow)
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The inverse of `Display`: digits with an optional leading `-`, and no
+/// `+`, whitespace or anything outside `i128`. Whether a negative amount is
+/// allowed is up to the caller; validation reports one as
+/// [`BalanceError::NegativeAmount`], with its address and denom.
+impl FromStr for Amount {
+    type Err = BalanceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BalanceError::InvalidAmount { amount: s.to_string() };
+        // `i128::from_str` alone would also take a leading `+`.
+        let digits = s.strip_prefix('-').unwrap_or(s);
+        if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        s.parse().map(Amount).map_err(|_| invalid())
+    }
+}
+
+/// Serialized as a decimal string such as `"1000"`. JSON numbers are usually
+/// read as `f64`, which can't represent amounts above 2^53 exactly, so
+/// deserializing only accepts strings, in the form `FromStr` takes.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Amount {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Amount {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let amount = String::deserialize(deserializer)?;
+        amount.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_arithmetic() {
+        assert_eq!(Amount::new(300) + Amount::new(200), Ok(Amount::new(500)));
+        assert_eq!(Amount::new(300) - Amount::new(500), Ok(Amount::new(-200)));
+        assert_eq!(Amount::new(i128::MAX) + Amount::new(1), Err(AmountOverflow));
+        assert_eq!(Amount::new(i128::MIN) - Amount::new(1), Err(AmountOverflow));
+        assert_eq!(i128::from(Amount::from(42)), 42);
+        assert_eq!("7".parse::<Amount>(), Ok(Amount::new(7)));
+        assert_eq!("-7".parse::<Amount>(), Ok(Amount::new(-7)));
+        assert_eq!(Amount::new(-7).to_string(), "-7");
+        for amount in ["", "-", "+7", "--7", " 7", "7 ", "1.5", "170141183460469231731687303715884105728"] {
+            assert_eq!(amount.parse::<Amount>(), Err(BalanceError::InvalidAmount { amount: amount.to_string() }), "{:?}", amount);
+        }
+        for amount in [i128::MIN, -1, 0, i128::MAX] {
+            assert_eq!(Amount::new(amount).to_string().parse(), Ok(Amount::new(amount)));
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use crate::{calculate_balance_changes, Balance, BalanceError, DenomDefinition, MultiSend};
+
+/// Runs [`calculate_balance_changes`] on tokio's blocking thread pool, so a
+/// huge transaction doesn't stall the async runtime it's awaited on. The
+/// inputs are moved onto the pool, hence owned rather than borrowed. A panic
+/// in the calculation is resumed in the awaiting task.
+pub async fn calculate_balance_changes_async(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+) -> Result<Vec<Balance>, BalanceError> {
+    tokio::task::spawn_blocking(move || calculate_balance_changes(&original_balances, &definitions, &multi_send_tx))
+        .await
+        .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::sample;
+
+    #[tokio::test]
+    async fn test_calculate_balance_changes_async() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+
+        let expected = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+        assert_eq!(calculate_balance_changes_async(original_balances, definitions, multi_send_tx).await, expected);
+    }
+}
@@ -0,0 +1,31 @@
+// What the crate needs beyond `core`, from `std` with the `std` feature and
+// from `alloc` without it, so the calculation is written once. Without `std`
+// there's no hasher to seed, so the internal maps and sets are B-trees
+// instead. Public types always use `BTreeMap` and `BTreeSet`, so the API is
+// the same either way.
+
+#[cfg(not(feature = "std"))]
+pub(crate) use alloc::{
+    borrow::Cow,
+    collections::{btree_map::Entry, BTreeMap, BTreeMap as Map, BTreeSet, BTreeSet as Set},
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+#[cfg(feature = "std")]
+pub(crate) use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap as Map, HashSet as Set},
+};
+
+// `HashMap::with_capacity`, or an empty map where B-trees have no capacity.
+#[cfg(feature = "std")]
+pub(crate) fn map_with_capacity<K, V>(capacity: usize) -> Map<K, V> {
+    Map::with_capacity(capacity)
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn map_with_capacity<K, V>(_capacity: usize) -> Map<K, V> {
+    Map::new()
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+
+use crate::{parse_amount, push_coin, Balance, Coin, DenomDefinition};
+
+/// A row that couldn't be read, with its 1-based line number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+/// Reads `address,denom,amount` rows into one [`Balance`] per address, in the
+/// order addresses first appear, with coins in row order. Amounts are decimal
+/// integers as in [`parse_amount`](crate::parse_amount).
+///
+/// Fields are taken as they are, without trimming, so a padded address or
+/// denom is rejected by the calculation as it would be from any other source.
+/// Quoting isn't supported, since no address, denom or amount contains a
+/// comma. Blank lines are skipped, and so is a first line
+/// that names the columns. A denom repeated for an address is kept as a second
+/// coin, for the calculation to reject like any other duplicate.
+pub fn read_balances_csv(reader: impl Read) -> Result<Vec<Balance>, CsvError> {
+    let mut balances = Vec::new();
+    for_each_row(reader, ["address", "denom", "amount"], |[address, denom, amount]| {
+        let amount = parse_amount(amount).map_err(|err| err.to_string())?;
+        push_coin(&mut balances, address.to_string(), Coin::new(denom, amount));
+        Ok(())
+    })?;
+    Ok(balances)
+}
+
+/// Reads `denom,issuer,burn_rate,commission_rate` rows, with rates as decimals
+/// such as `0.05`, into [`DenomDefinition`]s in row order. The format is
+/// otherwise the same as for [`read_balances_csv`].
+pub fn read_definitions_csv(reader: impl Read) -> Result<Vec<DenomDefinition>, CsvError> {
+    let mut definitions = Vec::new();
+    for_each_row(reader, ["denom", "issuer", "burn_rate", "commission_rate"], |[denom, issuer, burn_rate, commission_rate]| {
+        let burn_rate = burn_rate.parse().map_err(|err| format!("burn rate: {}", err))?;
+        let commission_rate = commission_rate.parse().map_err(|err| format!("commission rate: {}", err))?;
+        definitions.push(DenomDefinition::new(denom, issuer, burn_rate, commission_rate));
+        Ok(())
+    })?;
+    Ok(definitions)
+}
+
+// Splits every non-blank line into exactly `N` fields and hands them to
+// `f`, skipping a first line equal to `header`. Errors from `f` get the line
+// number attached.
+fn for_each_row<const N: usize>(reader: impl Read, header: [&str; N], mut f: impl FnMut([&str; N]) -> Result<(), String>) -> Result<(), CsvError> {
+    let mut seen_row = false;
+    for (index, line) in BufReader::new(reader).lines().enumerate() {
+        let error = |message: String| CsvError { line: index + 1, message };
+        let line = line.map_err(|err| error(err.to_string()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').collect();
+        let fields: [&str; N] = fields.try_into().map_err(|fields: Vec<&str>| error(format!("expected {} fields, found {}", N, fields.len())))?;
+        if !seen_row && fields == header {
+            seen_row = true;
+            continue;
+        }
+        seen_row = true;
+        f(fields).map_err(error)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{calculate_balance_changes, BalanceError};
+    use crate::tests::sample;
+
+    #[test]
+    fn test_read_csv() {
+        let balances = "address,denom,amount\naccount1,denom1,1000\naccount1,denom2,2000\n\naccount2,denom1,500\naccount2,denom2,1500\n";
+        let definitions = "denom,issuer,burn_rate,commission_rate\ndenom1,issuer_account,0.1,0.05\ndenom2,issuer_account,0.2,0.1\n";
+        let original_balances = read_balances_csv(balances.as_bytes()).unwrap();
+        let definitions = read_definitions_csv(definitions.as_bytes()).unwrap();
+        let (sample_balances, sample_definitions, multi_send_tx) = sample();
+        assert_eq!(original_balances, sample_balances);
+        assert_eq!(definitions, sample_definitions);
+
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap()[1],
+            Balance::new("account1", vec![Coin::new("denom1", -345), Coin::new("denom2", -1300)])
+        );
+    }
+
+    #[test]
+    fn test_malformed_rows_report_line() {
+        let error = read_balances_csv("account1,denom1,1000\naccount2,denom1\n".as_bytes()).unwrap_err();
+        assert_eq!(error, CsvError { line: 2, message: "expected 3 fields, found 2".to_string() });
+        let error = read_balances_csv("account1,denom1,1000\n\naccount2,denom1,-5\n".as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), format!("line 3: Amount \"-5\" is not a decimal integer from 0 to {}", i128::MAX));
+        let error = read_definitions_csv("denom1,issuer_account,0.1,5%\n".as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "line 1: commission rate: \"5%\" is not a valid decimal rate");
+    }
+
+    #[test]
+    fn test_padded_fields_are_kept() {
+        let original_balances = read_balances_csv("account1, denom1,1000\naccount2 ,denom1,500\n".as_bytes()).unwrap();
+        assert_eq!(original_balances, Balance::from_tuples(vec![("account1", vec![(" denom1", 1000)]), ("account2 ", vec![("denom1", 500)])]));
+        let (_, definitions, multi_send_tx) = sample();
+        assert_eq!(calculate_balance_changes(&original_balances, &definitions, &multi_send_tx), Err(BalanceError::InvalidDenom { denom: " denom1".to_string() }));
+        let error = read_balances_csv("account1,denom1, 1000\n".as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), format!("line 1: Amount \" 1000\" is not a decimal integer from 0 to {}", i128::MAX));
+    }
+}
@@ -0,0 +1,58 @@
+use serde::Deserialize;
+
+use crate::{calculate_balance_changes, Balance, DenomDefinition, MultiSend};
+
+#[derive(Deserialize)]
+struct Input {
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+}
+
+/// Takes the same JSON document as the command-line binary and returns the
+/// balance changes as JSON, or `{"error": "..."}` if the input doesn't parse
+/// or the transfer is rejected. Amounts are decimal strings both ways. With
+/// the `wasm` feature this is exported to JavaScript through `wasm-bindgen`.
+#[cfg_attr(feature = "wasm", wasm_bindgen::prelude::wasm_bindgen)]
+pub fn calculate_balance_changes_json(input: &str) -> String {
+    let result = serde_json::from_str::<Input>(input)
+        .map_err(|err| err.to_string())
+        .and_then(|input| calculate_balance_changes(&input.original_balances, &input.definitions, &input.multi_send_tx).map_err(|err| err.to_string()))
+        .and_then(|balance_changes| serde_json::to_string(&balance_changes).map_err(|err| err.to_string()));
+    match result {
+        Ok(json) => json,
+        Err(err) => serde_json::json!({ "error": err }).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_calculate_balance_changes_json() {
+        let input = include_str!("../tests/data/sample_transfer.json");
+        let balance_changes: Value = serde_json::from_str(&calculate_balance_changes_json(input)).unwrap();
+        assert_eq!(
+            balance_changes,
+            json!([
+                {"address": "account_recipient", "coins": [{"denom": "denom1", "amount": "500"}, {"denom": "denom2", "amount": "1500"}]},
+                {"address": "account1", "coins": [{"denom": "denom1", "amount": "-345"}, {"denom": "denom2", "amount": "-1300"}]},
+                {"address": "account2", "coins": [{"denom": "denom1", "amount": "-230"}, {"denom": "denom2", "amount": "-650"}]},
+                {"address": "issuer_account", "coins": [{"denom": "denom1", "amount": "25"}, {"denom": "denom2", "amount": "150"}]}
+            ])
+        );
+
+        let error: Value = serde_json::from_str(&calculate_balance_changes_json(&input.replace("\"1000\"", "\"100\""))).unwrap();
+        assert_eq!(error, json!({"error": "account1 does not have enough balance for denom1"}));
+
+        // A negative amount parses, and is rejected with its address and denom.
+        let error: Value = serde_json::from_str(&calculate_balance_changes_json(&input.replace("\"300\"", "\"-300\""))).unwrap();
+        let negative = crate::BalanceError::NegativeAmount { address: "account1".to_string(), denom: "denom1".to_string(), amount: -300 };
+        assert_eq!(error, json!({"error": negative.to_string()}));
+
+        let error: Value = serde_json::from_str(&calculate_balance_changes_json("not json")).unwrap();
+        assert!(error["error"].is_string());
+    }
+}
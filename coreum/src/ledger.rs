@@ -0,0 +1,172 @@
+use crate::compat::*;
+use crate::math::coin_sub_checked;
+use crate::{check_address, check_denom, Amount, Balance, BalanceError, Coin};
+
+/// Absolute balances per address and denom, for applying several transactions
+/// in sequence with [`apply_to_ledger`](crate::apply_to_ledger). Addresses and
+/// denoms that were never credited hold zero.
+///
+/// Balances are stored unsigned, so one can never go negative: any change that
+/// would take it below zero fails with [`BalanceError::InsufficientBalance`]
+/// and one above `i128::MAX` with [`BalanceError::Overflow`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ledger {
+    balances: Map<String, Map<String, u128>>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Ledger::default()
+    }
+
+    /// Builds a ledger from `balances`, rejecting any that lists a denom twice,
+    /// holds a negative amount or has an empty or whitespace-containing
+    /// address or denom.
+    pub fn from_balances(balances: &[Balance]) -> Result<Self, BalanceError> {
+        let (ledger, errors) = Ledger::collect(balances);
+        match errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(ledger),
+        }
+    }
+
+    // Like `from_balances`, but records every problem, keeping the first amount
+    // listed for a duplicated denom and leaving out negative ones.
+    pub(crate) fn collect(balances: &[Balance]) -> (Self, Vec<BalanceError>) {
+        let mut errors = Vec::new();
+        let mut ledger: Map<String, Map<String, u128>> = map_with_capacity(balances.len());
+        for balance in balances {
+            if let Err(error) = check_address(&balance.address) {
+                errors.push(error);
+            }
+            let mut coin_map: Map<String, u128> = map_with_capacity(balance.coins.len());
+            for coin in &balance.coins {
+                if let Err(error) = check_denom(&coin.denom) {
+                    errors.push(error);
+                }
+                match coin_map.entry(coin.denom.clone()) {
+                    Entry::Occupied(entry) => {
+                        errors.push(BalanceError::DuplicateDenom { address: balance.address.clone(), denom: entry.key().clone() });
+                    }
+                    Entry::Vacant(entry) => match u128::try_from(coin.amount.get()) {
+                        Ok(amount) => {
+                            entry.insert(amount);
+                        }
+                        Err(_) => errors.push(BalanceError::NegativeAmount {
+                            address: balance.address.clone(),
+                            denom: coin.denom.clone(),
+                            amount: coin.amount.get(),
+                        }),
+                    },
+                }
+            }
+            ledger.insert(balance.address.clone(), coin_map);
+        }
+        (Ledger { balances: ledger }, errors)
+    }
+
+    pub fn balance_of(&self, address: &str, denom: &str) -> i128 {
+        // Stored balances never exceed `i128::MAX`, see `changed`.
+        self.balances.get(address).and_then(|coins| coins.get(denom)).map_or(0, |&amount| amount as i128)
+    }
+
+    /// Whether `address` has an entry, even one holding nothing.
+    pub fn contains(&self, address: &str) -> bool {
+        self.balances.contains_key(address)
+    }
+
+    pub fn credit(&mut self, address: &str, denom: &str, amount: i128) -> Result<(), BalanceError> {
+        *self.entry(address, denom) = self.changed(address, denom, amount)?;
+        Ok(())
+    }
+
+    /// Takes `amount` from `address`, failing with
+    /// [`BalanceError::InsufficientBalance`] if it holds less.
+    pub fn debit(&mut self, address: &str, denom: &str, amount: i128) -> Result<(), BalanceError> {
+        *self.entry(address, denom) = self.changed(address, denom, coin_sub_checked(0, amount, denom)?)?;
+        Ok(())
+    }
+
+    // The balance `address` would hold after adding the signed `amount`,
+    // checked against both zero and `i128::MAX`.
+    fn changed(&self, address: &str, denom: &str, amount: i128) -> Result<u128, BalanceError> {
+        let have = self.balance_of(address, denom);
+        let balance = have as u128;
+        let changed = if amount >= 0 { balance.checked_add(amount as u128) } else { balance.checked_sub(amount.unsigned_abs()) };
+        match changed {
+            Some(changed) if changed <= i128::MAX as u128 => Ok(changed),
+            Some(_) => Err(BalanceError::Overflow { denom: denom.to_string() }),
+            None => Err(BalanceError::InsufficientBalance { address: address.to_string(), denom: denom.to_string(), have, need: amount.unsigned_abs() as i128 }),
+        }
+    }
+
+    // Applies signed per-address changes all or nothing: every new balance is
+    // computed before any is written, so an overflow leaves the ledger as it was.
+    pub(crate) fn apply(&mut self, balance_changes: &[Balance]) -> Result<(), BalanceError> {
+        let mut updated = Vec::new();
+        for balance in balance_changes {
+            for coin in &balance.coins {
+                updated.push((&balance.address, &coin.denom, self.changed(&balance.address, &coin.denom, coin.amount.get())?));
+            }
+        }
+        for (address, denom, amount) in updated {
+            *self.entry(address, denom) = amount;
+        }
+        Ok(())
+    }
+
+    // The resulting balance of every coin in `balance_changes`, in the same order.
+    pub(crate) fn balances_of(&self, balance_changes: Vec<Balance>) -> Vec<Balance> {
+        balance_changes
+            .into_iter()
+            .map(|balance| {
+                let coins = balance.coins.into_iter().map(|coin| Coin { amount: Amount::new(self.balance_of(&balance.address, &coin.denom)), denom: coin.denom }).collect();
+                Balance { address: balance.address, coins }
+            })
+            .collect()
+    }
+
+    fn entry(&mut self, address: &str, denom: &str) -> &mut u128 {
+        self.balances.entry(address.to_string()).or_default().entry(denom.to_string()).or_insert(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_credit_and_debit() {
+        let mut ledger = Ledger::from_balances(&[Balance::new("account1", vec![Coin::new("denom1", 100)])]).unwrap();
+        assert_eq!(ledger.balance_of("account1", "denom1"), 100);
+        assert_eq!(ledger.balance_of("account1", "denom2"), 0);
+        assert_eq!(ledger.balance_of("account2", "denom1"), 0);
+
+        ledger.credit("account2", "denom1", 40).unwrap();
+        ledger.debit("account1", "denom1", 40).unwrap();
+        assert_eq!(ledger.balance_of("account1", "denom1"), 60);
+        assert_eq!(ledger.balance_of("account2", "denom1"), 40);
+
+        assert_eq!(
+            ledger.debit("account2", "denom1", 41),
+            Err(BalanceError::InsufficientBalance { address: "account2".to_string(), denom: "denom1".to_string(), have: 40, need: 41 })
+        );
+        assert_eq!(ledger.credit("account2", "denom1", i128::MAX), Err(BalanceError::Overflow { denom: "denom1".to_string() }));
+        assert_eq!(ledger.balance_of("account2", "denom1"), 40);
+    }
+
+    #[test]
+    fn test_balances_cannot_go_negative() {
+        let balances = [Balance::new("account1", vec![Coin::new("denom1", 100)])];
+        let mut ledger = Ledger::from_balances(&balances).unwrap();
+        let insufficient = BalanceError::InsufficientBalance { address: "account1".to_string(), denom: "denom1".to_string(), have: 100, need: 150 };
+        assert_eq!(ledger.apply(&[Balance::new("account1", vec![Coin::new("denom1", -150)])]), Err(insufficient.clone()));
+        assert_eq!(ledger.credit("account1", "denom1", -150), Err(insufficient));
+        assert_eq!(ledger.balance_of("account1", "denom1"), 100);
+
+        assert_eq!(
+            Ledger::from_balances(&[Balance::new("account1", vec![Coin::new("denom1", -1)])]),
+            Err(BalanceError::NegativeAmount { address: "account1".to_string(), denom: "denom1".to_string(), amount: -1 })
+        );
+    }
+}
@@ -0,0 +1,3972 @@
+//! Balance change calculation for Coreum `MultiSend` transactions with
+//! per-denom burn and commission rates.
+
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
+
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
+use core::fmt;
+
+use compat::*;
+use math::{coin_add_checked, coin_mul_rate_checked, coin_sub_checked, coin_sub_saturating};
+
+mod amount;
+#[cfg(feature = "tokio")]
+mod blocking;
+mod compat;
+#[cfg(feature = "csv")]
+mod csv;
+#[cfg(feature = "json")]
+mod json;
+mod ledger;
+mod math;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod rate;
+
+pub use amount::{Amount, AmountOverflow};
+#[cfg(feature = "tokio")]
+pub use blocking::calculate_balance_changes_async;
+#[cfg(feature = "csv")]
+pub use csv::{read_balances_csv, read_definitions_csv, CsvError};
+#[cfg(feature = "json")]
+pub use json::calculate_balance_changes_json;
+pub use ledger::Ledger;
+#[cfg(feature = "rayon")]
+pub use parallel::calculate_balance_changes_parallel;
+pub use rate::{ParseRateError, Rate, RoundingMode};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Balance {
+    address: String,
+    coins: Vec<Coin>,
+}
+
+/// With the `serde` feature, `amount` is (de)serialized as a decimal string
+/// such as `"1000"` so large values survive JSON intact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Coin {
+    denom: String,
+    amount: Amount,
+}
+
+impl Balance {
+    pub fn new(address: impl Into<String>, coins: Vec<Coin>) -> Self {
+        Balance { address: address.into(), coins }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn coins(&self) -> &[Coin] {
+        &self.coins
+    }
+
+    /// Converts a list of tuples like those accepted by `From`. The orphan rule
+    /// rules out `impl From<Vec<_>> for Vec<Balance>`, hence the named function.
+    pub fn from_tuples<A: Into<String>, D: Into<String>>(balances: Vec<(A, Vec<(D, i128)>)>) -> Vec<Balance> {
+        balances.into_iter().map(Balance::from).collect()
+    }
+
+    /// Sums coins that share a denom into one, kept where the denom first
+    /// appears. Amounts are added as given, so the result is validated like
+    /// any other coin. Fails with [`BalanceError::Overflow`] if a sum doesn't
+    /// fit, leaving `self` unchanged.
+    pub fn normalize(&mut self) -> Result<(), BalanceError> {
+        let mut merged: Vec<Coin> = Vec::with_capacity(self.coins.len());
+        for coin in &self.coins {
+            match merged.iter_mut().find(|merged| merged.denom == coin.denom) {
+                Some(merged) => merged.amount = Amount::new(coin_add_checked(merged.amount.get(), coin.amount.get(), &coin.denom)?),
+                None => merged.push(coin.clone()),
+            }
+        }
+        self.coins = merged;
+        Ok(())
+    }
+}
+
+/// `("account1", vec![("denom1", 1000)])`, for compact fixtures.
+impl<A: Into<String>, D: Into<String>> From<(A, Vec<(D, i128)>)> for Balance {
+    fn from((address, coins): (A, Vec<(D, i128)>)) -> Self {
+        Balance::new(address, coins.into_iter().map(Coin::from).collect())
+    }
+}
+
+impl<D: Into<String>> From<(D, i128)> for Coin {
+    fn from((denom, amount): (D, i128)) -> Self {
+        Coin::new(denom, amount)
+    }
+}
+
+impl Coin {
+    pub fn new(denom: impl Into<String>, amount: i128) -> Self {
+        Coin { denom: denom.into(), amount: Amount::new(amount) }
+    }
+
+    pub fn denom(&self) -> &str {
+        &self.denom
+    }
+
+    pub fn amount(&self) -> Amount {
+        self.amount
+    }
+
+    /// The amount in whole units with `precision` decimal places, trailing
+    /// zeros dropped: `1234500` with precision 6 is `1.2345`. The denom is
+    /// left out.
+    pub fn format_with_precision(&self, precision: u8) -> String {
+        let precision = usize::from(precision);
+        let digits = format!("{:0>width$}", self.amount.get().unsigned_abs(), width = precision + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - precision);
+        let fraction = fraction.trim_end_matches('0');
+        let sign = if self.amount.get() < 0 { "-" } else { "" };
+        if fraction.is_empty() {
+            format!("{}{}", sign, whole)
+        } else {
+            format!("{}{}.{}", sign, whole, fraction)
+        }
+    }
+
+    /// Like [`Coin::new`], with the amount given as a string for
+    /// [`parse_amount`].
+    pub fn from_str_amount(denom: impl Into<String>, amount: &str) -> Result<Self, BalanceError> {
+        Ok(Coin::new(denom, parse_amount(amount)?))
+    }
+}
+
+/// The amount followed by the denom, as in Cosmos SDK coin strings: `1000denom1`.
+impl fmt::Display for Coin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.amount, self.denom)
+    }
+}
+
+/// `account1: 1000denom1, 2000denom2`, or just `account1:` without coins.
+impl fmt::Display for Balance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.address)?;
+        for (i, coin) in self.coins.iter().enumerate() {
+            write!(f, "{} {}", if i == 0 { "" } else { "," }, coin)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses an amount sent as a decimal string, as chains do to keep large
+/// values out of JSON floats. Only ASCII digits are accepted: no sign, no
+/// whitespace, and nothing above `i128::MAX`.
+pub fn parse_amount(amount: &str) -> Result<i128, BalanceError> {
+    if amount.starts_with('-') {
+        return Err(BalanceError::InvalidAmount { amount: amount.to_string() });
+    }
+    amount.parse::<Amount>().map(Amount::get)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MultiSend {
+    inputs: Vec<Balance>,
+    outputs: Vec<Balance>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DenomDefinition {
+    denom: String,
+    issuer: String,
+    burn_rate: Rate,
+    commission_rate: Rate,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    max_burn: Option<i128>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    max_commission: Option<i128>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    precision: Option<u8>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    fee_exempt: Vec<String>,
+}
+
+impl MultiSend {
+    pub fn new(inputs: Vec<Balance>, outputs: Vec<Balance>) -> Self {
+        MultiSend { inputs, outputs }
+    }
+
+    pub fn inputs(&self) -> &[Balance] {
+        &self.inputs
+    }
+
+    pub fn outputs(&self) -> &[Balance] {
+        &self.outputs
+    }
+}
+
+/// Builds a [`MultiSend`] one coin at a time. Coins for an address already
+/// on the same side are appended to its `Balance`, so each address appears
+/// once per side, in the order first added.
+#[derive(Debug, Clone, Default)]
+pub struct MultiSendBuilder {
+    inputs: Vec<Balance>,
+    outputs: Vec<Balance>,
+}
+
+impl MultiSendBuilder {
+    pub fn new() -> Self {
+        MultiSendBuilder::default()
+    }
+
+    pub fn input(mut self, address: impl Into<String>, denom: impl Into<String>, amount: i128) -> Self {
+        push_coin(&mut self.inputs, address.into(), Coin::new(denom, amount));
+        self
+    }
+
+    pub fn output(mut self, address: impl Into<String>, denom: impl Into<String>, amount: i128) -> Self {
+        push_coin(&mut self.outputs, address.into(), Coin::new(denom, amount));
+        self
+    }
+
+    pub fn build(self) -> MultiSend {
+        MultiSend { inputs: self.inputs, outputs: self.outputs }
+    }
+}
+
+// Repeating a denom for the same address adds a second coin rather than
+// summing, leaving it to validation to reject like any other duplicate.
+fn push_coin(balances: &mut Vec<Balance>, address: String, coin: Coin) {
+    match balances.iter_mut().find(|balance| balance.address == address) {
+        Some(balance) => balance.coins.push(coin),
+        None => balances.push(Balance { address, coins: vec![coin] }),
+    }
+}
+
+impl DenomDefinition {
+    pub fn new(denom: impl Into<String>, issuer: impl Into<String>, burn_rate: Rate, commission_rate: Rate) -> Self {
+        DenomDefinition { denom: denom.into(), issuer: issuer.into(), burn_rate, commission_rate, max_burn: None, max_commission: None, precision: None, fee_exempt: Vec::new() }
+    }
+
+    /// Same as [`new`](DenomDefinition::new) with both rates given in basis
+    /// points, so `with_bps("denom1", "issuer", 1000, 500)` burns 10% and takes
+    /// 5% commission.
+    pub fn with_bps(denom: impl Into<String>, issuer: impl Into<String>, burn_bps: u32, commission_bps: u32) -> Self {
+        DenomDefinition::new(denom, issuer, Rate::from_bps(burn_bps), Rate::from_bps(commission_bps))
+    }
+
+    /// Caps the total burn charged for this denom in one transaction. When the
+    /// rate would charge more, the capped total is split across senders the
+    /// same way.
+    pub fn with_max_burn(mut self, max_burn: i128) -> Self {
+        self.max_burn = Some(max_burn);
+        self
+    }
+
+    /// Caps the total commission charged for this denom in one transaction,
+    /// like [`with_max_burn`](DenomDefinition::with_max_burn).
+    pub fn with_max_commission(mut self, max_commission: i128) -> Self {
+        self.max_commission = Some(max_commission);
+        self
+    }
+
+    /// Records how many decimal places one whole unit of the denom has, for
+    /// display with [`Coin::format_with_precision`]. Calculations ignore it.
+    pub fn with_precision(mut self, precision: u8) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    /// Lets these addresses send the denom fee-free, like its issuer: their
+    /// inputs are left out of the fee base and charged neither burn nor
+    /// commission. They are still charged as recipients.
+    pub fn with_fee_exempt(mut self, addresses: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.fee_exempt.extend(addresses.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn denom(&self) -> &str {
+        &self.denom
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn burn_rate(&self) -> Rate {
+        self.burn_rate
+    }
+
+    pub fn commission_rate(&self) -> Rate {
+        self.commission_rate
+    }
+
+    pub fn max_burn(&self) -> Option<i128> {
+        self.max_burn
+    }
+
+    pub fn max_commission(&self) -> Option<i128> {
+        self.max_commission
+    }
+
+    pub fn precision(&self) -> Option<u8> {
+        self.precision
+    }
+
+    pub fn fee_exempt(&self) -> &[String] {
+        &self.fee_exempt
+    }
+
+    // Whether `address` is charged for sending this denom.
+    fn charges_sender(&self, address: &str) -> bool {
+        address != self.issuer && !self.fee_exempt.iter().any(|exempt| exempt == address)
+    }
+
+    // The burn and commission totals charged on `base`.
+    fn fee_totals(&self, rounding: RoundingMode, fee_basis: FeeBasis, base: i128) -> Result<(i128, i128), BalanceError> {
+        let burn = fee_total(self.burn_rate, self.max_burn, rounding, base, &self.denom)?;
+        let commission_base = fee_basis.commission_base(base, burn, &self.denom)?;
+        let commission = fee_total(self.commission_rate, self.max_commission, rounding, commission_base, &self.denom)?;
+        Ok((burn, commission))
+    }
+
+    /// Checks that the denom and issuer are well-formed (see
+    /// [`calculate_balance_changes`]), that both rates lie in `[0, 1]`, `0`
+    /// and `1` included, and that neither fee cap is negative. An empty issuer
+    /// fails with [`BalanceError::InvalidDefinition`], since commission would
+    /// have no one to go to.
+    pub fn validate(&self) -> Result<(), BalanceError> {
+        check_denom(&self.denom)?;
+        if self.issuer.is_empty() {
+            return Err(BalanceError::InvalidDefinition { denom: self.denom.clone() });
+        }
+        check_address(&self.issuer)?;
+        for address in &self.fee_exempt {
+            check_address(address)?;
+        }
+        for rate in [self.burn_rate, self.commission_rate] {
+            if rate < Rate::ZERO || rate > Rate::ONE {
+                return Err(BalanceError::InvalidRate { denom: self.denom.clone(), rate });
+            }
+        }
+        if let Some(cap) = [self.max_burn, self.max_commission].into_iter().flatten().find(|&cap| cap < 0) {
+            return Err(BalanceError::InvalidFeeCap { denom: self.denom.clone(), cap });
+        }
+        Ok(())
+    }
+}
+
+/// Burn and commission actually deducted from senders, per denom. Denoms only
+/// sent by their issuer don't appear. `minted` holds what issuers sent beyond
+/// their balance under [`CalculationOptions::issuer_can_overdraw`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransferSummary {
+    pub burned: BTreeMap<String, i128>,
+    pub commission: BTreeMap<String, i128>,
+    pub minted: BTreeMap<String, i128>,
+    /// The `(address, denom)` pairs among the balance changes where the
+    /// address is the denom's issuer, and so moves it fee-free.
+    pub issuers: BTreeSet<(String, String)>,
+    /// Fee totals worth a second look, in denom order, burn before
+    /// commission. Only filled in under [`CalculationOptions::collect_warnings`].
+    pub warnings: Vec<Warning>,
+    /// Only filled in under [`CalculationOptions::collect_diagnostics`].
+    pub diagnostics: Option<Diagnostics>,
+}
+
+/// The intermediate values the fees of one denom were worked out from.
+/// `non_issuer_input_sum` is what charged senders sent, which their fee shares
+/// are proportional to, and `fee_base` is what the rates were applied to: the
+/// smaller of that and the outputs not going to the issuer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DenomDiagnostics {
+    pub non_issuer_input_sum: i128,
+    pub output_total: i128,
+    pub fee_base: i128,
+}
+
+/// Per-denom [`DenomDiagnostics`] for every denom in a transaction, for
+/// debugging how fees were split.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    pub denoms: BTreeMap<String, DenomDiagnostics>,
+}
+
+/// Per-denom totals of one transaction, from [`transaction_report`].
+/// `transferred` is the sum of the outputs and `net_supply` the net of all
+/// balance changes, which is `-burned`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DenomReport {
+    pub transferred: i128,
+    pub burned: i128,
+    pub commission: i128,
+    pub net_supply: i128,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransactionReport {
+    pub denoms: BTreeMap<String, DenomReport>,
+}
+
+/// Per-denom movements of one transaction, from [`check_conservation`]:
+/// `debited` is what left senders and `credited` what recipients and the
+/// issuer gained, both as positive sums of balance changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DenomConservation {
+    pub debited: i128,
+    pub credited: i128,
+    pub burned: i128,
+    pub commission: i128,
+}
+
+/// `balanced` holds when, for every denom, everything debited was either
+/// credited or burned.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConservationReport {
+    pub denoms: BTreeMap<String, DenomConservation>,
+    pub balanced: bool,
+}
+
+impl ConservationReport {
+    /// Tallies `balance_changes` against the burn and commission in `summary`,
+    /// as calculated with the default options, where nothing is minted and
+    /// burns leave circulation. Changes from elsewhere, such as another
+    /// implementation, can be checked the same way.
+    pub fn from_changes(balance_changes: &[Balance], summary: &TransferSummary) -> Result<Self, BalanceError> {
+        let mut denoms: BTreeMap<String, DenomConservation> = BTreeMap::new();
+        for coin in balance_changes.iter().flat_map(|balance| &balance.coins) {
+            let denom = denoms.entry(coin.denom.clone()).or_default();
+            if coin.amount.get() < 0 {
+                denom.debited = coin_sub_checked(denom.debited, coin.amount.get(), &coin.denom)?;
+            } else {
+                denom.credited = coin_add_checked(denom.credited, coin.amount.get(), &coin.denom)?;
+            }
+        }
+        for (denom, &burned) in &summary.burned {
+            denoms.entry(denom.clone()).or_default().burned = burned;
+        }
+        for (denom, &commission) in &summary.commission {
+            denoms.entry(denom.clone()).or_default().commission = commission;
+        }
+        let mut balanced = true;
+        for (name, denom) in &denoms {
+            balanced &= coin_add_checked(denom.credited, denom.burned, name)? == denom.debited;
+        }
+        Ok(ConservationReport { denoms, balanced })
+    }
+}
+
+/// Counts describing the shape of a transaction, from [`stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransactionStats {
+    /// Distinct addresses among the inputs and outputs.
+    pub affected_accounts: usize,
+    /// Distinct denoms among the inputs and outputs.
+    pub distinct_denoms: usize,
+    /// Number of input entries, one per sending address.
+    pub total_inputs: usize,
+    /// Number of output entries, one per receiving address.
+    pub total_outputs: usize,
+}
+
+/// What a denom's commission rate is applied to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum FeeBasis {
+    /// Burn and commission are both charged on the fee base.
+    #[default]
+    Independent,
+    /// Commission is charged on the fee base plus the burn total, after
+    /// rounding and any cap.
+    Sequential,
+}
+
+impl FeeBasis {
+    fn commission_base(self, base: i128, burn: i128, denom: &str) -> Result<i128, BalanceError> {
+        match self {
+            FeeBasis::Independent => Ok(base),
+            FeeBasis::Sequential => coin_add_checked(base, burn, denom),
+        }
+    }
+}
+
+/// Options for [`calculate_balance_changes_with_options`]. The default is the
+/// strict behavior of [`calculate_balance_changes`]. Options with an
+/// `address_validator` compare equal only if they hold the same function
+/// pointer, which isn't guaranteed for the same function.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[allow(unpredictable_function_pointer_comparisons)]
+pub struct CalculationOptions {
+    /// How each denom's burn and commission totals are rounded before being
+    /// split across senders.
+    pub rounding: RoundingMode,
+    /// Transfer denoms that have no [`DenomDefinition`] with zero burn and
+    /// commission instead of failing with [`BalanceError::MissingDefinition`].
+    pub allow_undefined_denoms: bool,
+    /// Let an issuer send more of its own denom than it holds. The issuer's
+    /// balance only drops to zero and the rest is minted, as reported in
+    /// [`TransferSummary::minted`].
+    pub issuer_can_overdraw: bool,
+    /// Credit burned amounts to this address, one coin per burned denom,
+    /// instead of removing them from circulation.
+    pub burn_address: Option<String>,
+    /// [`normalize`](Balance::normalize) every balance, original and in the
+    /// transaction, instead of failing with [`BalanceError::DuplicateDenom`].
+    pub merge_duplicate_denoms: bool,
+    /// Let a denom's inputs exceed its outputs instead of failing with
+    /// [`BalanceError::InputOutputMismatch`]. The remainder goes to
+    /// `imbalance_sink`, or is burned if there is none, and isn't charged burn
+    /// or commission. Outputs exceeding inputs are still rejected.
+    pub allow_imbalance: bool,
+    /// Credit the remainder of an imbalanced denom to this address, one coin
+    /// per denom, after any burn address.
+    pub imbalance_sink: Option<String>,
+    /// Extra check on every input, output and issuer address, and on the burn
+    /// address and imbalance sink, such as a bech32 prefix; an address it
+    /// rejects fails with [`BalanceError::InvalidAddress`]. It only runs on
+    /// addresses that are non-empty and free of whitespace.
+    pub address_validator: Option<fn(&str) -> bool>,
+    /// Reject a transaction with more input entries than this with
+    /// [`BalanceError::LimitExceeded`], before any other check runs.
+    pub max_inputs: Option<usize>,
+    /// Reject a transaction with more distinct denoms than this across its
+    /// inputs and outputs, like `max_inputs`.
+    pub max_distinct_denoms: Option<usize>,
+    /// Return balances sorted by address and each one's coins sorted by
+    /// denom, instead of in transaction order, so equal results serialize and
+    /// hash the same.
+    pub sort_output: bool,
+    /// Report fee totals that were rounded from less than one unit in
+    /// [`TransferSummary::warnings`], see
+    /// [`calculate_balance_changes_with_options_and_summary`].
+    pub collect_warnings: bool,
+    /// Whether commission is charged on the same base as the burn or on the
+    /// base plus the burn.
+    pub fee_basis: FeeBasis,
+    /// Report the sums each denom's fees were worked out from in
+    /// [`TransferSummary::diagnostics`].
+    pub collect_diagnostics: bool,
+}
+
+/// How one input coin's deduction splits up: `base` is the amount sent, and
+/// `burn` and `commission` are the sender's fee shares, zero for an issuer
+/// sending its own denom. The sender loses `base + burn + commission`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeductionDetail {
+    pub address: String,
+    pub denom: String,
+    pub base: i128,
+    pub burn: i128,
+    pub commission: i128,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BalanceError {
+    InsufficientBalance {
+        address: String,
+        denom: String,
+        have: i128,
+        need: i128,
+    },
+    MissingDefinition {
+        denom: String,
+    },
+    InputOutputMismatch {
+        denom: String,
+        input_total: i128,
+        output_total: i128,
+    },
+    Overflow {
+        denom: String,
+    },
+    NegativeAmount {
+        address: String,
+        denom: String,
+        amount: i128,
+    },
+    DuplicateDenom {
+        address: String,
+        denom: String,
+    },
+    InvalidRate {
+        denom: String,
+        rate: Rate,
+    },
+    UnknownSender {
+        address: String,
+    },
+    InvalidDenom {
+        denom: String,
+    },
+    InvalidAddress {
+        address: String,
+    },
+    InvalidAmount {
+        amount: String,
+    },
+    InvalidFeeCap {
+        denom: String,
+        cap: i128,
+    },
+    DuplicateAddress {
+        address: String,
+    },
+    Mismatch {
+        address: String,
+        denom: String,
+        got: i128,
+        expected: i128,
+    },
+    InvalidDefinition {
+        denom: String,
+    },
+    LimitExceeded {
+        limit: String,
+        max: usize,
+    },
+    /// Outputs to a denom's issuer that the inputs don't cover, taken as the
+    /// caller listing commission itself. `claimed` is the uncovered part and
+    /// `collected` the commission the calculation credits to the issuer.
+    CommissionMismatch {
+        denom: String,
+        claimed: i128,
+        collected: i128,
+    },
+}
+
+impl fmt::Display for BalanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BalanceError::InsufficientBalance { address, denom, .. } => {
+                write!(f, "{} does not have enough balance for {}", address, denom)
+            }
+            BalanceError::MissingDefinition { denom } => {
+                write!(f, "Denomination {} does not have a definition", denom)
+            }
+            BalanceError::InputOutputMismatch { denom, input_total, output_total } => {
+                write!(
+                    f,
+                    "Input and output does not match for {}: inputs total {}, outputs total {}, difference {}",
+                    denom,
+                    input_total,
+                    output_total,
+                    coin_sub_saturating(*output_total, *input_total)
+                )
+            }
+            BalanceError::Overflow { denom } => {
+                write!(f, "Amount overflow for {}", denom)
+            }
+            BalanceError::NegativeAmount { address, denom, amount } => {
+                write!(f, "{} has a negative amount {} for {}", address, amount, denom)
+            }
+            BalanceError::DuplicateDenom { address, denom } => {
+                write!(f, "{} lists {} more than once", address, denom)
+            }
+            BalanceError::InvalidRate { denom, rate } => {
+                write!(f, "Denomination {} has rate {} outside [0, 1]", denom, rate)
+            }
+            BalanceError::UnknownSender { address } => {
+                write!(f, "{} sends coins but has no original balance", address)
+            }
+            BalanceError::InvalidDenom { denom } => {
+                write!(f, "Denomination {:?} is empty or contains whitespace", denom)
+            }
+            BalanceError::InvalidAddress { address } => {
+                write!(f, "Address {:?} is empty, contains whitespace or was rejected by the address validator", address)
+            }
+            BalanceError::InvalidAmount { amount } => {
+                write!(f, "Amount {:?} is not a decimal integer from 0 to {}", amount, i128::MAX)
+            }
+            BalanceError::InvalidFeeCap { denom, cap } => {
+                write!(f, "Denomination {} has negative fee cap {}", denom, cap)
+            }
+            BalanceError::DuplicateAddress { address } => {
+                write!(f, "{} is listed more than once on the same side of the transaction", address)
+            }
+            BalanceError::Mismatch { address, denom, got, expected } => {
+                write!(f, "{} changes by {} {} but {} was expected", address, got, denom, expected)
+            }
+            BalanceError::InvalidDefinition { denom } => {
+                write!(f, "Denomination {} has no issuer", denom)
+            }
+            BalanceError::LimitExceeded { limit, max } => {
+                write!(f, "Transaction has more than {} {}", max, limit)
+            }
+            BalanceError::CommissionMismatch { denom, claimed, collected } => {
+                write!(f, "Outputs to the issuer of {} claim {} not covered by inputs; commission of {} is credited by the calculation", denom, claimed, collected)
+            }
+        }
+    }
+}
+
+impl core::error::Error for BalanceError {}
+
+/// Which of a denom's two fees a [`Warning`] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FeeKind {
+    Burn,
+    Commission,
+}
+
+impl fmt::Display for FeeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeeKind::Burn => write!(f, "burn"),
+            FeeKind::Commission => write!(f, "commission"),
+        }
+    }
+}
+
+/// Something about a transaction that succeeded that an integrator may want to
+/// show or log, from [`TransferSummary::warnings`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Warning {
+    /// A non-zero rate came to less than one unit on the denom's fee base, so
+    /// the rounding mode alone decided the total: `charged` is 1 when rounded
+    /// up and 0 when rounded down.
+    DustFeeRounded {
+        denom: String,
+        fee: FeeKind,
+        charged: i128,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::DustFeeRounded { denom, fee, charged } => {
+                write!(f, "The {} on {} is less than one unit and was rounded to {}", fee, denom, charged)
+            }
+        }
+    }
+}
+
+// Denoms and addresses are used verbatim as map keys, so a stray space would
+// silently fail to match. They are rejected rather than trimmed, so the caller
+// sees the mistake instead of a guess at what they meant.
+fn is_well_formed(identifier: &str) -> bool {
+    !identifier.is_empty() && !identifier.chars().any(char::is_whitespace)
+}
+
+fn check_denom(denom: &str) -> Result<(), BalanceError> {
+    if is_well_formed(denom) {
+        Ok(())
+    } else {
+        Err(BalanceError::InvalidDenom { denom: denom.to_string() })
+    }
+}
+
+fn check_address(address: &str) -> Result<(), BalanceError> {
+    if is_well_formed(address) {
+        Ok(())
+    } else {
+        Err(BalanceError::InvalidAddress { address: address.to_string() })
+    }
+}
+
+// Per-address deltas in first-seen address order, with coins in first-seen
+// denom order. Adding to an address or denom that is already present merges
+// into the existing entry.
+#[derive(Default)]
+struct BalanceChanges {
+    positions: Map<String, usize>,
+    balances: Vec<Balance>,
+}
+
+impl BalanceChanges {
+    fn add(&mut self, address: &str, denom: &str, amount: i128) -> Result<(), BalanceError> {
+        let total = self.amount_mut(address, denom);
+        *total = Amount::new(coin_add_checked(total.get(), amount, denom)?);
+        Ok(())
+    }
+
+    // The entry for `address` and `denom`, starting at zero.
+    fn amount_mut(&mut self, address: &str, denom: &str) -> &mut Amount {
+        let position = match self.positions.get(address) {
+            Some(&position) => position,
+            None => {
+                self.positions.insert(address.to_string(), self.balances.len());
+                self.balances.push(Balance { address: address.to_string(), coins: Vec::new() });
+                self.balances.len() - 1
+            }
+        };
+        let coins = &mut self.balances[position].coins;
+        let index = match coins.iter().position(|coin| coin.denom == denom) {
+            Some(index) => index,
+            None => {
+                coins.push(Coin::new(denom, 0));
+                coins.len() - 1
+            }
+        };
+        &mut coins[index].amount
+    }
+
+    // Coins that net to zero are dropped, and so are addresses left with none.
+    fn into_balances(self) -> Vec<Balance> {
+        let mut balances = self.balances;
+        for balance in &mut balances {
+            balance.coins.retain(|coin| coin.amount != Amount::ZERO);
+        }
+        balances.retain(|balance| !balance.coins.is_empty());
+        balances
+    }
+}
+
+// Like `*totals.entry(denom).or_insert(0) += amount`, but only allocates the
+// key the first time a denom is seen.
+fn add_to_total(totals: &mut BTreeMap<String, i128>, denom: &str, amount: i128) -> Result<(), BalanceError> {
+    match totals.get_mut(denom) {
+        Some(total) => *total = coin_add_checked(*total, amount, denom)?,
+        None => {
+            totals.insert(denom.to_string(), amount);
+        }
+    }
+    Ok(())
+}
+
+// `base * rate`, rounded as `rounding` says and lowered to `cap` if above it.
+fn fee_total(rate: Rate, cap: Option<i128>, rounding: RoundingMode, base: i128, denom: &str) -> Result<i128, BalanceError> {
+    let total = coin_mul_rate_checked(base, rate, rounding, denom)?;
+    Ok(cap.map_or(total, |cap| total.min(cap)))
+}
+
+// Splits `total` across `amounts` in proportion to each amount's part of
+// `amount_sum`. Shares are floored first and the leftover units go one each to
+// the largest remainders, earliest sender first on ties, so the shares always
+// add up to exactly `total`.
+fn allocate_fee(total: i128, amounts: &[i128], amount_sum: i128, denom: &str) -> Result<Vec<i128>, BalanceError> {
+    let overflow = || BalanceError::Overflow { denom: denom.to_string() };
+    if amount_sum == 0 {
+        return Ok(vec![0; amounts.len()]);
+    }
+    let mut shares = Vec::with_capacity(amounts.len());
+    let mut remainders = Vec::with_capacity(amounts.len());
+    for &amount in amounts {
+        let (share, remainder) = rate::mul_div_rem(total, amount, amount_sum).ok_or_else(overflow)?;
+        shares.push(share);
+        remainders.push(remainder);
+    }
+    let leftover = total - shares.iter().sum::<i128>();
+    let mut by_remainder: Vec<usize> = (0..amounts.len()).collect();
+    by_remainder.sort_by(|&a, &b| remainders[b].cmp(&remainders[a]));
+    for &index in by_remainder.iter().take(leftover as usize) {
+        shares[index] += 1;
+    }
+    Ok(shares)
+}
+
+// State built while validating a transaction, reused by the calculation.
+// `fee_shares` holds the burn and commission owed by each input coin, indexed
+// like `inputs[i].coins[j]`; issuer coins owe nothing. `minted` holds, by the
+// same index, how much of an issuer's own-denom coin it doesn't hold.
+// `imbalance` holds, per denom, how much its inputs exceed its outputs when
+// that's allowed. `warnings` and `diagnostics` are only filled in when
+// they're collected.
+struct Validated<'a> {
+    definition_map: BTreeMap<&'a str, &'a DenomDefinition>,
+    fee_shares: Vec<Vec<(i128, i128)>>,
+    minted: Map<(usize, usize), i128>,
+    imbalance: Map<String, i128>,
+    warnings: Vec<Warning>,
+    diagnostics: Option<Diagnostics>,
+}
+
+// Returns every problem found, in the order the checks run, so the first error
+// is the one a fail-fast caller would have stopped at.
+fn validate<'a>(
+    ledger: &Ledger,
+    ledger_errors: Vec<BalanceError>,
+    definitions: &'a [DenomDefinition],
+    multi_send_tx: &MultiSend,
+    options: &CalculationOptions,
+) -> Result<Validated<'a>, Vec<BalanceError>> {
+    let mut errors = Vec::new();
+    match check(ledger, ledger_errors, definitions, multi_send_tx, options, &mut errors) {
+        Ok(Some(validated)) => Ok(validated),
+        Ok(None) => Err(errors),
+        Err(error) => {
+            errors.push(error);
+            Err(errors)
+        }
+    }
+}
+
+fn first_error(errors: Vec<BalanceError>) -> BalanceError {
+    errors.into_iter().next().expect("validation failed without an error")
+}
+
+// Each address may appear once per side. Balance checks look at one entry at a
+// time, so a sender listed twice could spend its balance twice.
+fn check_duplicate_addresses(multi_send_tx: &MultiSend, errors: &mut Vec<BalanceError>) {
+    for side in [&multi_send_tx.inputs, &multi_send_tx.outputs] {
+        let mut addresses = Set::new();
+        for balance in side {
+            if !addresses.insert(&balance.address) {
+                errors.push(BalanceError::DuplicateAddress { address: balance.address.clone() });
+            }
+        }
+    }
+}
+
+// The well-formedness check every address gets, plus the caller's own.
+fn validate_address(address: &str, options: &CalculationOptions) -> Result<(), BalanceError> {
+    check_address(address)?;
+    match options.address_validator {
+        Some(is_valid) if !is_valid(address) => Err(BalanceError::InvalidAddress { address: address.to_string() }),
+        _ => Ok(()),
+    }
+}
+
+// Checks every input and output entry on its own, coins or not: its address,
+// and each coin's denom, uniqueness and sign.
+fn check_shape(multi_send_tx: &MultiSend, options: &CalculationOptions, errors: &mut Vec<BalanceError>) {
+    check_duplicate_addresses(multi_send_tx, errors);
+    for balance in multi_send_tx.inputs.iter().chain(&multi_send_tx.outputs) {
+        if let Err(error) = validate_address(&balance.address, options) {
+            errors.push(error);
+        }
+        let mut denoms = Set::new();
+        for coin in &balance.coins {
+            if let Err(error) = check_denom(&coin.denom) {
+                errors.push(error);
+            }
+            if !denoms.insert(&coin.denom) {
+                errors.push(BalanceError::DuplicateDenom {
+                    address: balance.address.clone(),
+                    denom: coin.denom.clone(),
+                });
+            }
+            if coin.amount.get() < 0 {
+                errors.push(BalanceError::NegativeAmount {
+                    address: balance.address.clone(),
+                    denom: coin.denom.clone(),
+                    amount: coin.amount.get(),
+                });
+            }
+        }
+    }
+}
+
+fn check_limits(multi_send_tx: &MultiSend, options: &CalculationOptions) -> Result<(), BalanceError> {
+    if let Some(max) = options.max_inputs.filter(|&max| multi_send_tx.inputs.len() > max) {
+        return Err(BalanceError::LimitExceeded { limit: "inputs".to_string(), max });
+    }
+    if let Some(max) = options.max_distinct_denoms {
+        // Stops as soon as the limit is passed, so the set never grows past it.
+        let mut denoms = Set::new();
+        for coin in multi_send_tx.inputs.iter().chain(&multi_send_tx.outputs).flat_map(|balance| &balance.coins) {
+            if denoms.insert(coin.denom.as_str()) && denoms.len() > max {
+                return Err(BalanceError::LimitExceeded { limit: "distinct denoms".to_string(), max });
+            }
+        }
+    }
+    Ok(())
+}
+
+// Checks run in phases and each phase records all of its errors, but a failed
+// phase stops the later ones, whose results would be meaningless: shapes and
+// rates first, then sender balances, definitions and the input/output match,
+// then the fee-inclusive balance check. An overflow stops everything
+// immediately since the totals can no longer be trusted.
+// `ledger_errors` are the problems found building the ledger, reported after
+// the transaction's own shape errors.
+fn check<'a>(
+    ledger: &Ledger,
+    ledger_errors: Vec<BalanceError>,
+    definitions: &'a [DenomDefinition],
+    multi_send_tx: &MultiSend,
+    options: &CalculationOptions,
+    errors: &mut Vec<BalanceError>,
+) -> Result<Option<Validated<'a>>, BalanceError> {
+    // Limits go first, so an oversized transaction is turned away before any
+    // per-coin work or allocation sized by it.
+    check_limits(multi_send_tx, options)?;
+    check_shape(multi_send_tx, options, errors);
+    let validate_address = |address: &str| validate_address(address, options);
+
+    errors.extend(ledger_errors);
+    for address in [&options.burn_address, &options.imbalance_sink] {
+        if let Some(Err(error)) = address.as_deref().map(validate_address) {
+            errors.push(error);
+        }
+    }
+
+    let mut definition_map: BTreeMap<&str, &DenomDefinition> = BTreeMap::new();
+    for definition in definitions {
+        if let Err(error) = definition.validate().and_then(|_| validate_address(&definition.issuer)) {
+            errors.push(error);
+        }
+        definition_map.insert(&definition.denom, definition);
+    }
+    if !errors.is_empty() {
+        return Ok(None);
+    }
+
+    // Per-denom state is kept in B-trees: iteration order, and so the order
+    // errors are reported in, is the same on every run, and denoms picked to
+    // collide can't degrade the lookups.
+    let mut input_total: BTreeMap<&str, i128> = BTreeMap::new();
+    let mut output_total: BTreeMap<&str, i128> = BTreeMap::new();
+    let mut non_issuer_input_sum: BTreeMap<&str, i128> = BTreeMap::new();
+    let mut non_issuer_output_sum: BTreeMap<&str, i128> = BTreeMap::new();
+    let mut issuer_output_sum: BTreeMap<&str, i128> = BTreeMap::new();
+    let mut minted = Map::new();
+    let mints = |balance: &Balance, coin: &Coin| {
+        options.issuer_can_overdraw && definition_map.get(coin.denom.as_str()).is_some_and(|definition| balance.address == definition.issuer)
+    };
+    for (i, balance) in multi_send_tx.inputs.iter().enumerate() {
+        let known = ledger.contains(&balance.address);
+        if !known && balance.coins.iter().any(|coin| !mints(balance, coin)) {
+            errors.push(BalanceError::UnknownSender { address: balance.address.clone() });
+        }
+        for (j, coin) in balance.coins.iter().enumerate() {
+            // Checked for every coin, whoever sends it and whether or not they
+            // can cover it, so a balance problem doesn't hide a missing definition.
+            let definition = definition_map.get(coin.denom.as_str());
+            if definition.is_none() && !options.allow_undefined_denoms {
+                errors.push(BalanceError::MissingDefinition { denom: coin.denom.clone() });
+            }
+            if mints(balance, coin) {
+                let held = ledger.balance_of(&balance.address, &coin.denom).clamp(0, coin.amount.get());
+                if held < coin.amount.get() {
+                    minted.insert((i, j), coin.amount.get() - held);
+                }
+            } else if known {
+                // A known sender that doesn't hold the denom has a balance of zero.
+                let balance_amount = ledger.balance_of(&balance.address, &coin.denom);
+                if balance_amount >= coin.amount.get() {
+                    if definition.is_some_and(|definition| definition.charges_sender(&balance.address)) {
+                        let non_issuer_input = non_issuer_input_sum.entry(&coin.denom).or_insert(0);
+                        *non_issuer_input = coin_add_checked(*non_issuer_input, coin.amount.get(), &coin.denom)?;
+                    }
+                } else {
+                    errors.push(BalanceError::InsufficientBalance {
+                        address: balance.address.clone(),
+                        denom: coin.denom.clone(),
+                        have: balance_amount,
+                        need: coin.amount.get(),
+                    });
+                }
+            }
+            // Every coin counts towards the totals, even one that failed a check
+            // above, so the match check doesn't report a knock-on error.
+            let total_input = input_total.entry(&coin.denom).or_insert(0);
+            *total_input = coin_add_checked(*total_input, coin.amount.get(), &coin.denom)?;
+        }
+    }
+    for balance in &multi_send_tx.outputs {
+        for coin in &balance.coins {
+            let total_output = output_total.entry(&coin.denom).or_insert(0);
+            *total_output = coin_add_checked(*total_output, coin.amount.get(), &coin.denom)?;
+            if let Some(definition) = definition_map.get(coin.denom.as_str()) {
+                let sum = if balance.address == definition.issuer { &mut issuer_output_sum } else { &mut non_issuer_output_sum };
+                let total = sum.entry(&coin.denom).or_insert(0);
+                *total = coin_add_checked(*total, coin.amount.get(), &coin.denom)?;
+            }
+        }
+    }
+    // Outputs to the issuer are ordinary transfers and count here like any
+    // other. Commission isn't part of the transaction's outputs; it comes out
+    // of the senders' balances on top of their inputs and never unbalances it.
+    // So when the outputs exceed the inputs by no more than what goes to the
+    // issuer, the excess is commission listed as an output, and is reported
+    // next to what was actually collected. Denoms that are only output get a
+    // zero input total, so one sorted pass reports mismatches from the
+    // smallest denom up.
+    for denom in output_total.keys() {
+        input_total.entry(denom).or_insert(0);
+    }
+    let mut imbalance = Map::new();
+    for (denom, total_input) in &input_total {
+        let total_output = *output_total.get(denom).unwrap_or(&0);
+        if options.allow_imbalance && total_output < *total_input {
+            imbalance.insert(denom.to_string(), total_input - total_output);
+        } else if let Some(claimed) = total_output.checked_sub(*total_input).filter(|&excess| excess > 0 && excess <= issuer_output_sum.get(denom).copied().unwrap_or(0)) {
+            let base = non_issuer_input_sum.get(denom).copied().unwrap_or(0).min(non_issuer_output_sum.get(denom).copied().unwrap_or(0));
+            let (_, collected) = definition_map[denom].fee_totals(options.rounding, options.fee_basis, base)?;
+            errors.push(BalanceError::CommissionMismatch { denom: denom.to_string(), claimed, collected });
+        } else if total_output != *total_input {
+            errors.push(BalanceError::InputOutputMismatch {
+                denom: denom.to_string(),
+                input_total: *total_input,
+                output_total: total_output,
+            });
+        }
+    }
+    if !errors.is_empty() {
+        return Ok(None);
+    }
+    let diagnostics = options.collect_diagnostics.then(|| {
+        let denom_diagnostics = |denom: &str| {
+            let non_issuer_input_sum = non_issuer_input_sum.get(denom).copied().unwrap_or(0);
+            let fee_base = non_issuer_input_sum.min(non_issuer_output_sum.get(denom).copied().unwrap_or(0));
+            DenomDiagnostics { non_issuer_input_sum, output_total: output_total.get(denom).copied().unwrap_or(0), fee_base }
+        };
+        Diagnostics { denoms: input_total.keys().map(|&denom| (denom.to_string(), denom_diagnostics(denom))).collect() }
+    });
+
+    let mut fee_shares: Vec<Vec<(i128, i128)>> = multi_send_tx.inputs.iter().map(|balance| vec![(0, 0); balance.coins.len()]).collect();
+    let mut non_issuer_senders: Map<&str, Vec<(usize, usize)>> = Map::new();
+    let mut warnings = Vec::new();
+    for (i, balance) in multi_send_tx.inputs.iter().enumerate() {
+        for (j, coin) in balance.coins.iter().enumerate() {
+            // Zero-rate denoms skip the split entirely: their shares are zero by
+            // definition, so there's nothing to compute or round.
+            if definition_map.get(coin.denom.as_str()).is_some_and(|definition| {
+                definition.charges_sender(&balance.address) && !(definition.burn_rate.is_zero() && definition.commission_rate.is_zero())
+            }) {
+                non_issuer_senders.entry(&coin.denom).or_default().push((i, j));
+            }
+        }
+    }
+    for (denom, senders) in &non_issuer_senders {
+        let definition = &definition_map[*denom];
+        let non_issuer_input = non_issuer_input_sum[denom];
+        // Only value moving between non-issuers is charged: Coreum's asset-ft
+        // module applies neither burn nor commission to the issuer's side of a
+        // transfer, whether it sends or receives. Inputs and outputs balance
+        // overall, so issuer inputs can only add to the outputs; what caps the
+        // base is the share of outputs paid back to the issuer.
+        let base = non_issuer_input.min(non_issuer_output_sum.get(denom).copied().unwrap_or(0));
+        let amounts: Vec<i128> = senders.iter().map(|&(i, j)| multi_send_tx.inputs[i].coins[j].amount.get()).collect();
+        let (total_burn, total_commission) = definition.fee_totals(options.rounding, options.fee_basis, base)?;
+        if options.collect_warnings {
+            let commission_base = options.fee_basis.commission_base(base, total_burn, denom)?;
+            for (fee, rate, base, charged) in [(FeeKind::Burn, definition.burn_rate, base, total_burn), (FeeKind::Commission, definition.commission_rate, commission_base, total_commission)] {
+                // Rounded down the total is zero, yet the rate and base aren't.
+                if !rate.is_zero() && base > 0 && coin_mul_rate_checked(base, rate, RoundingMode::Floor, denom)? == 0 {
+                    warnings.push(Warning::DustFeeRounded { denom: denom.to_string(), fee, charged });
+                }
+            }
+        }
+        let burns = allocate_fee(total_burn, &amounts, non_issuer_input, denom)?;
+        let commissions = allocate_fee(total_commission, &amounts, non_issuer_input, denom)?;
+        for (&(i, j), (burn, commission)) in senders.iter().zip(burns.into_iter().zip(commissions)) {
+            // Arguments are only evaluated when a logger has debug enabled.
+            log::debug!(
+                "{}: {} sends {} of base {}, burn share {}, commission share {}",
+                denom,
+                multi_send_tx.inputs[i].address,
+                multi_send_tx.inputs[i].coins[j].amount,
+                base,
+                burn,
+                commission
+            );
+            fee_shares[i][j] = (burn, commission);
+        }
+    }
+
+    // Non-issuers pay burn and commission on top of what they send, so the
+    // earlier check against the sent amount alone isn't enough.
+    for (balance, shares) in multi_send_tx.inputs.iter().zip(&fee_shares) {
+        for (coin, &(burn, commission)) in balance.coins.iter().zip(shares) {
+            if burn == 0 && commission == 0 {
+                continue;
+            }
+            let have = ledger.balance_of(&balance.address, &coin.denom);
+            let need = coin_add_checked(coin.amount.get(), burn, &coin.denom).and_then(|amount| coin_add_checked(amount, commission, &coin.denom))?;
+            if have < need {
+                errors.push(BalanceError::InsufficientBalance {
+                    address: balance.address.clone(),
+                    denom: coin.denom.clone(),
+                    have,
+                    need,
+                });
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Ok(None);
+    }
+
+    // Denoms were visited in map order.
+    warnings.sort_unstable();
+    Ok(Some(Validated { definition_map, fee_shares, minted, imbalance, warnings, diagnostics }))
+}
+
+/// Runs every check [`calculate_balance_changes`] performs and returns the
+/// first failure, without computing any balance changes.
+pub fn validate_multi_send(original_balances: &[Balance], definitions: &[DenomDefinition], multi_send_tx: &MultiSend) -> Result<(), BalanceError> {
+    let (ledger, ledger_errors) = Ledger::collect(original_balances);
+    validate(&ledger, ledger_errors, definitions, multi_send_tx, &CalculationOptions::default()).map(|_| ()).map_err(first_error)
+}
+
+/// Whether [`validate_multi_send`] would accept the transaction, for callers
+/// that only need a yes or no.
+pub fn is_valid(original_balances: &[Balance], definitions: &[DenomDefinition], multi_send_tx: &MultiSend) -> bool {
+    validate_multi_send(original_balances, definitions, multi_send_tx).is_ok()
+}
+
+/// Computes the balance change of every account touched by `multi_send_tx`.
+///
+/// Every original, input and output coin amount must be non-negative; zero
+/// amounts are allowed and simply move nothing. Each `Balance`, whether an
+/// original balance, an input or an output, may hold at most one coin per denom;
+/// duplicates are rejected rather than summed. Likewise an address may appear
+/// at most once among the inputs and once among the outputs, or the
+/// transaction fails with [`BalanceError::DuplicateAddress`]; use
+/// [`MultiSendBuilder`] to merge them. Denoms and addresses must be
+/// non-empty and free of whitespace; they are rejected, not trimmed, with
+/// [`BalanceError::InvalidDenom`] or [`BalanceError::InvalidAddress`]. A
+/// transaction with no inputs and no outputs is valid and changes nothing; one
+/// with inputs but no outputs fails with [`BalanceError::InputOutputMismatch`].
+///
+/// The result holds one entry per affected address: outputs first, in the
+/// order given, followed by inputs, in the order given. An address that is
+/// both a recipient and a sender is merged into a single entry at its first
+/// position, carrying its net change per denom. Coins keep the order in which
+/// their denom first appears for that address. Zero changes are left out: a
+/// coin that nets to zero is dropped, and so is an address left with no coins,
+/// such as one that only sends zero amounts.
+///
+/// Non-issuer senders pay burn and commission on top of what they send. For
+/// each denom the totals are `rate * min(non-issuer inputs, non-issuer
+/// outputs)`, so value sent to or from the issuer is not charged, nor value
+/// sent by an address in the definition's
+/// [`fee_exempt`](DenomDefinition::with_fee_exempt) list, rounded up
+/// by default (see [`CalculationOptions::rounding`]) and lowered to the
+/// definition's [`max_burn`](DenomDefinition::max_burn) and
+/// [`max_commission`](DenomDefinition::max_commission) if set. They are split
+/// across the non-issuer senders in proportion to what they send, with the
+/// shares adding up to exactly those totals. The commission is credited to the
+/// denom's issuer, which is appended after the inputs unless it already has an
+/// entry, even when it holds nothing and takes no part in the transaction;
+/// the burn is removed from circulation. Commission is never listed in
+/// `multi_send_tx`: every output, including one to the issuer, is an ordinary
+/// transfer that inputs must cover. Outputs to the issuer beyond what the
+/// inputs cover are taken as claimed commission and fail with
+/// [`BalanceError::CommissionMismatch`], so they can't mint anything.
+/// A sender whose balance covers the amount sent but not the fees on top of it
+/// is rejected with [`BalanceError::InsufficientBalance`]. Fees are never
+/// capped to fit: rounded up, the fees on a tiny transfer can exceed the
+/// amount itself, as with a burn of 1 on 1 unit sent at a 0.9 burn rate.
+pub fn calculate_balance_changes(original_balances: &[Balance], definitions: &[DenomDefinition], multi_send_tx: &MultiSend) -> Result<Vec<Balance>, BalanceError> {
+    calculate_balance_changes_with_options(original_balances, definitions, multi_send_tx, &CalculationOptions::default())
+}
+
+/// Same as [`calculate_balance_changes`], with the strict defaults relaxed as
+/// `options` allows.
+pub fn calculate_balance_changes_with_options(
+    original_balances: &[Balance],
+    definitions: &[DenomDefinition],
+    multi_send_tx: &MultiSend,
+    options: &CalculationOptions,
+) -> Result<Vec<Balance>, BalanceError> {
+    calculate(original_balances, definitions, multi_send_tx, options).map(|(balance_changes, _)| balance_changes)
+}
+
+/// Same as [`calculate_balance_changes`], but also reports the burn and
+/// commission collected per denom.
+pub fn calculate_balance_changes_with_summary(
+    original_balances: &[Balance],
+    definitions: &[DenomDefinition],
+    multi_send_tx: &MultiSend,
+) -> Result<(Vec<Balance>, TransferSummary), BalanceError> {
+    calculate(original_balances, definitions, multi_send_tx, &CalculationOptions::default())
+}
+
+/// Same as [`calculate_balance_changes_with_options`], but also returns the
+/// [`TransferSummary`], with any warnings `options` asks for.
+pub fn calculate_balance_changes_with_options_and_summary(
+    original_balances: &[Balance],
+    definitions: &[DenomDefinition],
+    multi_send_tx: &MultiSend,
+    options: &CalculationOptions,
+) -> Result<(Vec<Balance>, TransferSummary), BalanceError> {
+    calculate(original_balances, definitions, multi_send_tx, options)
+}
+
+fn calculate(
+    original_balances: &[Balance],
+    definitions: &[DenomDefinition],
+    multi_send_tx: &MultiSend,
+    options: &CalculationOptions,
+) -> Result<(Vec<Balance>, TransferSummary), BalanceError> {
+    // Merging needs copies to change; otherwise everything is borrowed.
+    let mut original_balances = Cow::Borrowed(original_balances);
+    let mut multi_send_tx = Cow::Borrowed(multi_send_tx);
+    if options.merge_duplicate_denoms {
+        let multi_send_tx = multi_send_tx.to_mut();
+        for balance in original_balances.to_mut().iter_mut().chain(&mut multi_send_tx.inputs).chain(&mut multi_send_tx.outputs) {
+            balance.normalize()?;
+        }
+    }
+    let (mut ledger, ledger_errors) = Ledger::collect(&original_balances);
+    let validated = validate(&ledger, ledger_errors, definitions, &multi_send_tx, options).map_err(first_error)?;
+    let Executed { mut balance_changes, summary } = execute(validated, &multi_send_tx, options.burn_address.as_deref(), options.imbalance_sink.as_deref())?;
+    ledger.apply(&balance_changes)?;
+    if options.sort_output {
+        balance_changes.sort_unstable_by(|a, b| a.address.cmp(&b.address));
+        for balance in &mut balance_changes {
+            balance.coins.sort_unstable_by(|a, b| a.denom.cmp(&b.denom));
+        }
+    }
+    Ok((balance_changes, summary))
+}
+
+/// The entry [`calculate_balance_changes`] returns for `address`, or `None`
+/// if the transaction doesn't change its balance. Fails whenever
+/// [`calculate_balance_changes`] does.
+pub fn balance_change_for(
+    address: &str,
+    original_balances: &[Balance],
+    definitions: &[DenomDefinition],
+    multi_send_tx: &MultiSend,
+) -> Result<Option<Balance>, BalanceError> {
+    let (balance_changes, _) = calculate(original_balances, definitions, multi_send_tx, &CalculationOptions::default())?;
+    Ok(balance_changes.into_iter().find(|balance| balance.address == address))
+}
+
+/// Same as [`calculate_balance_changes`], but hands each change to `f` in the
+/// same order instead of returning them, so a caller writing them out keeps no
+/// second copy. `f` is only called once the whole transaction has passed, so a
+/// rejected one emits nothing. The changes themselves are still gathered
+/// before the first call: an address's entry isn't final until every coin
+/// has been processed.
+pub fn for_each_balance_change(
+    original_balances: &[Balance],
+    definitions: &[DenomDefinition],
+    multi_send_tx: &MultiSend,
+    f: impl FnMut(Balance),
+) -> Result<(), BalanceError> {
+    let (balance_changes, _) = calculate(original_balances, definitions, multi_send_tx, &CalculationOptions::default())?;
+    balance_changes.into_iter().for_each(f);
+    Ok(())
+}
+
+/// Same as [`calculate_balance_changes`], but reports every validation error
+/// instead of only the first. No balances are changed unless all checks
+/// pass. Some errors hide others: when amounts, duplicates or rates are
+/// invalid, balances and the input/output match aren't checked, and fees are
+/// only checked once everything else passes.
+pub fn calculate_balance_changes_collecting(
+    original_balances: &[Balance],
+    definitions: &[DenomDefinition],
+    multi_send_tx: &MultiSend,
+) -> Result<Vec<Balance>, Vec<BalanceError>> {
+    let (mut ledger, ledger_errors) = Ledger::collect(original_balances);
+    let validated = validate(&ledger, ledger_errors, definitions, multi_send_tx, &CalculationOptions::default())?;
+    let Executed { balance_changes, .. } = execute(validated, multi_send_tx, None, None).map_err(|error| vec![error])?;
+    ledger.apply(&balance_changes).map_err(|error| vec![error])?;
+    Ok(balance_changes)
+}
+
+/// Same as [`calculate_balance_changes`], but returns the balance each affected
+/// address ends up with instead of the change: every coin holds original plus
+/// change, in the same order. Denoms an address holds but the transaction
+/// doesn't touch are left out, since they are unchanged.
+pub fn apply_multi_send(original_balances: &[Balance], definitions: &[DenomDefinition], multi_send_tx: &MultiSend) -> Result<Vec<Balance>, BalanceError> {
+    let (mut ledger, ledger_errors) = Ledger::collect(original_balances);
+    let validated = validate(&ledger, ledger_errors, definitions, multi_send_tx, &CalculationOptions::default()).map_err(first_error)?;
+    let Executed { balance_changes, .. } = execute(validated, multi_send_tx, None, None)?;
+    ledger.apply(&balance_changes)?;
+    Ok(ledger.balances_of(balance_changes))
+}
+
+/// Same as [`apply_multi_send`], but only charges the fees: each non-issuer
+/// sender loses its burn and commission shares and each issuer gains its
+/// commission, while the amounts sent stay where they are. The result holds
+/// the senders in input order followed by the issuers, for previewing or
+/// reconciling fees apart from the transfer itself. The whole transaction is
+/// still validated.
+pub fn apply_fees_only(original_balances: &[Balance], definitions: &[DenomDefinition], multi_send_tx: &MultiSend) -> Result<Vec<Balance>, BalanceError> {
+    let (mut ledger, ledger_errors) = Ledger::collect(original_balances);
+    let Validated { definition_map, fee_shares, .. } = validate(&ledger, ledger_errors, definitions, multi_send_tx, &CalculationOptions::default()).map_err(first_error)?;
+    let mut balance_changes = BalanceChanges::default();
+    let mut commissions = BalanceChanges::default();
+    for (balance, shares) in multi_send_tx.inputs.iter().zip(fee_shares) {
+        for (coin, (burn, commission)) in balance.coins.iter().zip(shares) {
+            let fees = coin_add_checked(burn, commission, &coin.denom)?;
+            balance_changes.add(&balance.address, &coin.denom, -fees)?;
+            if commission != 0 {
+                commissions.add(&definition_map[coin.denom.as_str()].issuer, &coin.denom, commission)?;
+            }
+        }
+    }
+    for issuer in commissions.into_balances() {
+        for coin in issuer.coins {
+            balance_changes.add(&issuer.address, &coin.denom, coin.amount.get())?;
+        }
+    }
+    let balance_changes = balance_changes.into_balances();
+    ledger.apply(&balance_changes)?;
+    Ok(ledger.balances_of(balance_changes))
+}
+
+/// Runs `multi_send_tx` against `ledger` and returns the balance changes, like
+/// [`calculate_balance_changes`] does for a fresh set of balances. The ledger
+/// is only updated when the whole transaction succeeds, so transactions can be
+/// applied one after another and a rejected one leaves no trace.
+pub fn apply_to_ledger(ledger: &mut Ledger, definitions: &[DenomDefinition], multi_send_tx: &MultiSend) -> Result<Vec<Balance>, BalanceError> {
+    let validated = validate(ledger, Vec::new(), definitions, multi_send_tx, &CalculationOptions::default()).map_err(first_error)?;
+    let Executed { balance_changes, .. } = execute(validated, multi_send_tx, None, None)?;
+    ledger.apply(&balance_changes)?;
+    Ok(balance_changes)
+}
+
+/// Runs the same checks as [`calculate_balance_changes`] and returns one
+/// [`DeductionDetail`] per input coin, in input order.
+pub fn calculate_deductions(
+    original_balances: &[Balance],
+    definitions: &[DenomDefinition],
+    multi_send_tx: &MultiSend,
+) -> Result<Vec<DeductionDetail>, BalanceError> {
+    let (ledger, ledger_errors) = Ledger::collect(original_balances);
+    let Validated { fee_shares, .. } = validate(&ledger, ledger_errors, definitions, multi_send_tx, &CalculationOptions::default()).map_err(first_error)?;
+    let mut deductions = Vec::new();
+    for (balance, shares) in multi_send_tx.inputs.iter().zip(fee_shares) {
+        for (coin, (burn, commission)) in balance.coins.iter().zip(shares) {
+            deductions.push(DeductionDetail { address: balance.address.clone(), denom: coin.denom.clone(), base: coin.amount.get(), burn, commission });
+        }
+    }
+    Ok(deductions)
+}
+
+/// Works out what each sender pays in total when the outputs are the amounts
+/// recipients should receive. Outputs are always net of fees, with burn and
+/// commission charged to the senders on top, so this returns `multi_send_tx`'s
+/// inputs with every coin raised to its amount plus the sender's fee shares.
+/// An issuer's own-denom coins are unchanged. Runs the same checks as
+/// [`calculate_balance_changes`], so each sender must hold its gross amount.
+pub fn gross_inputs(original_balances: &[Balance], definitions: &[DenomDefinition], multi_send_tx: &MultiSend) -> Result<Vec<Balance>, BalanceError> {
+    let (ledger, ledger_errors) = Ledger::collect(original_balances);
+    let Validated { fee_shares, .. } = validate(&ledger, ledger_errors, definitions, multi_send_tx, &CalculationOptions::default()).map_err(first_error)?;
+    let mut gross = Vec::with_capacity(multi_send_tx.inputs.len());
+    for (balance, shares) in multi_send_tx.inputs.iter().zip(fee_shares) {
+        let mut coins = Vec::with_capacity(balance.coins.len());
+        for (coin, (burn, commission)) in balance.coins.iter().zip(shares) {
+            let amount = coin_add_checked(coin.amount.get(), burn, &coin.denom).and_then(|amount| coin_add_checked(amount, commission, &coin.denom))?;
+            coins.push(Coin::new(&coin.denom, amount));
+        }
+        gross.push(Balance::new(&balance.address, coins));
+    }
+    Ok(gross)
+}
+
+/// Returns the largest amount a sole sender holding `balance` can send when
+/// burn and commission are charged on top at the given rates, for "send max".
+/// Fee totals are rounded up as in [`calculate_balance_changes`], so the amount
+/// plus its fees never exceeds `balance`. A zero or negative balance, or a
+/// negative rate, gives 0.
+pub fn max_sendable(balance: i128, burn_rate: Rate, commission_rate: Rate) -> i128 {
+    let fits = |base: i128| {
+        let fee = |rate: Rate| rate.mul_rounded(base, RoundingMode::default());
+        let gross = fee(burn_rate).zip(fee(commission_rate)).and_then(|(burn, commission)| base.checked_add(burn)?.checked_add(commission));
+        gross.is_some_and(|gross| gross <= balance)
+    };
+    // Base plus fees only grows with the base, so search for the last fit.
+    let (mut low, mut high) = (0, balance.max(0));
+    while low < high {
+        let middle = high - (high - low) / 2;
+        if fits(middle) {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    low
+}
+
+/// Runs the same checks as [`calculate_balance_changes`] and returns how the
+/// total supply of each denom in the transaction changes: the net of all its
+/// balance changes, which is minus the burn since everything else moves
+/// between accounts.
+pub fn supply_delta(original_balances: &[Balance], definitions: &[DenomDefinition], multi_send_tx: &MultiSend) -> Result<BTreeMap<String, i128>, BalanceError> {
+    let (balance_changes, _) = calculate(original_balances, definitions, multi_send_tx, &CalculationOptions::default())?;
+    let mut deltas = BTreeMap::new();
+    for coin in balance_changes.iter().flat_map(|balance| &balance.coins) {
+        add_to_total(&mut deltas, &coin.denom, coin.amount.get())?;
+    }
+    Ok(deltas)
+}
+
+/// Runs [`calculate_balance_changes`] and checks the result against
+/// `expected`, failing with [`BalanceError::Mismatch`] on the first change
+/// that differs, or with the calculation's own error. Changes are compared
+/// per address and denom, ignoring order, and a missing coin counts as zero.
+pub fn calculate_and_verify(original_balances: &[Balance], definitions: &[DenomDefinition], multi_send_tx: &MultiSend, expected: &[Balance]) -> Result<(), BalanceError> {
+    let balance_changes = calculate_balance_changes(original_balances, definitions, multi_send_tx)?;
+    let got = coin_amounts(&balance_changes).0;
+    let (want, errors) = coin_amounts(expected);
+    if let Some(error) = errors.into_iter().next() {
+        return Err(error);
+    }
+    for balance in balance_changes.iter().chain(expected) {
+        for coin in &balance.coins {
+            let key = (balance.address.as_str(), coin.denom.as_str());
+            let (got, expected) = (got.get(&key).copied().unwrap_or(0), want.get(&key).copied().unwrap_or(0));
+            if got != expected {
+                return Err(BalanceError::Mismatch { address: balance.address.clone(), denom: coin.denom.clone(), got, expected });
+            }
+        }
+    }
+    Ok(())
+}
+
+// Signed amounts per address and denom, for comparing sets of changes, which
+// a `Ledger` can't hold. Problems are reported as `Ledger::collect` does,
+// keeping the first amount listed for a duplicated denom.
+fn coin_amounts(balances: &[Balance]) -> (Map<(&str, &str), i128>, Vec<BalanceError>) {
+    let mut errors = Vec::new();
+    let mut amounts = Map::new();
+    for balance in balances {
+        if let Err(error) = check_address(&balance.address) {
+            errors.push(error);
+        }
+        for coin in &balance.coins {
+            if let Err(error) = check_denom(&coin.denom) {
+                errors.push(error);
+            }
+            match amounts.entry((balance.address.as_str(), coin.denom.as_str())) {
+                Entry::Occupied(_) => errors.push(BalanceError::DuplicateDenom { address: balance.address.clone(), denom: coin.denom.clone() }),
+                Entry::Vacant(entry) => {
+                    entry.insert(coin.amount.get());
+                }
+            }
+        }
+    }
+    (amounts, errors)
+}
+
+/// The per-address, per-denom change from `before` to `after`, such as the
+/// original balances and what [`apply_multi_send`] returns for them. A coin
+/// missing from either side counts as zero, so addresses that only appear in
+/// `after` show up with their whole balance. Unchanged coins are left out, and
+/// addresses come in the order they appear in `after`, then `before`.
+///
+/// Fails with the first problem in `before`, then `after`, as
+/// [`Ledger::collect`] reports them, or with [`BalanceError::Overflow`] if a
+/// delta doesn't fit in an `i128`.
+pub fn diff_balances(before: &[Balance], after: &[Balance]) -> Result<Vec<Balance>, BalanceError> {
+    let ((old, old_errors), (new, new_errors)) = (coin_amounts(before), coin_amounts(after));
+    if let Some(error) = old_errors.into_iter().chain(new_errors).next() {
+        return Err(error);
+    }
+    let mut seen = Set::new();
+    let mut deltas = BalanceChanges::default();
+    for balance in after.iter().chain(before) {
+        for coin in &balance.coins {
+            let key = (balance.address.as_str(), coin.denom.as_str());
+            if !seen.insert(key) {
+                continue;
+            }
+            let delta = coin_sub_checked(new.get(&key).copied().unwrap_or(0), old.get(&key).copied().unwrap_or(0), &coin.denom)?;
+            if delta != 0 {
+                deltas.add(&balance.address, &coin.denom, delta)?;
+            }
+        }
+    }
+    Ok(deltas.into_balances())
+}
+
+/// Sums balance changes from several results, such as the parts of a sharded
+/// transaction, into one entry per address with one coin per denom.
+/// Addresses and coins come in the order they first appear, going through
+/// `results` in order. Changes that net to zero are left out. Fails with
+/// [`BalanceError::Overflow`] if a sum doesn't fit in an `i128`.
+pub fn merge_balance_changes(results: Vec<Vec<Balance>>) -> Result<Vec<Balance>, BalanceError> {
+    let mut merged = BalanceChanges::default();
+    for balance in results.iter().flatten() {
+        for coin in &balance.coins {
+            merged.add(&balance.address, &coin.denom, coin.amount.get())?;
+        }
+    }
+    Ok(merged.into_balances())
+}
+
+/// Runs [`calculate_balance_changes`] and tallies what it actually moved, per
+/// denom, into a [`ConservationReport`]. This is the check debug builds run on
+/// every transfer, reported instead of asserted, for audits.
+pub fn check_conservation(original_balances: &[Balance], definitions: &[DenomDefinition], multi_send_tx: &MultiSend) -> Result<ConservationReport, BalanceError> {
+    let (balance_changes, summary) = calculate(original_balances, definitions, multi_send_tx, &CalculationOptions::default())?;
+    ConservationReport::from_changes(&balance_changes, &summary)
+}
+
+/// Runs [`calculate_balance_changes`] and sums up, per denom the transaction
+/// touches, what was transferred, burned and paid in commission and how the
+/// total supply changed.
+pub fn transaction_report(original_balances: &[Balance], definitions: &[DenomDefinition], multi_send_tx: &MultiSend) -> Result<TransactionReport, BalanceError> {
+    let (balance_changes, summary) = calculate(original_balances, definitions, multi_send_tx, &CalculationOptions::default())?;
+    let mut report = TransactionReport::default();
+    for coin in multi_send_tx.outputs.iter().flat_map(|balance| &balance.coins) {
+        let denom = report.denoms.entry(coin.denom.clone()).or_default();
+        denom.transferred = coin_add_checked(denom.transferred, coin.amount.get(), &coin.denom)?;
+    }
+    for coin in balance_changes.iter().flat_map(|balance| &balance.coins) {
+        let denom = report.denoms.entry(coin.denom.clone()).or_default();
+        denom.net_supply = coin_add_checked(denom.net_supply, coin.amount.get(), &coin.denom)?;
+    }
+    for (denom, burned) in summary.burned {
+        report.denoms.entry(denom).or_default().burned = burned;
+    }
+    for (denom, commission) in summary.commission {
+        report.denoms.entry(denom).or_default().commission = commission;
+    }
+    Ok(report)
+}
+
+/// Estimates the `(burn, commission)` totals per denom that `multi_send_tx`
+/// would be charged, without looking at any balances: the transaction may
+/// still be rejected for other reasons when actually run. Every denom a
+/// non-issuer sends appears, even when its fees are zero. Fails on a sent
+/// denom without a definition, an invalid definition or an overflow.
+pub fn estimate_fees(definitions: &[DenomDefinition], multi_send_tx: &MultiSend) -> Result<BTreeMap<String, (i128, i128)>, BalanceError> {
+    let mut definition_map: Map<&str, &DenomDefinition> = map_with_capacity(definitions.len());
+    for definition in definitions {
+        definition.validate()?;
+        definition_map.insert(&definition.denom, definition);
+    }
+    let non_issuer_sum = |balances: &[Balance], charged: fn(&DenomDefinition, &str) -> bool| {
+        let mut sums = BTreeMap::new();
+        for balance in balances {
+            for coin in &balance.coins {
+                let definition = definition_map.get(coin.denom.as_str()).ok_or_else(|| BalanceError::MissingDefinition { denom: coin.denom.clone() })?;
+                if charged(definition, &balance.address) {
+                    add_to_total(&mut sums, &coin.denom, coin.amount.get())?;
+                }
+            }
+        }
+        Ok(sums)
+    };
+    let non_issuer_input_sum = non_issuer_sum(&multi_send_tx.inputs, DenomDefinition::charges_sender)?;
+    let non_issuer_output_sum = non_issuer_sum(&multi_send_tx.outputs, |definition, address| address != definition.issuer)?;
+    let mut fees = BTreeMap::new();
+    for (denom, non_issuer_input) in non_issuer_input_sum {
+        // The same base as the actual calculation, see `check`.
+        let base = non_issuer_input.min(non_issuer_output_sum.get(&denom).copied().unwrap_or(0));
+        let totals = definition_map[denom.as_str()].fee_totals(RoundingMode::default(), FeeBasis::default(), base)?;
+        fees.insert(denom, totals);
+    }
+    Ok(fees)
+}
+
+/// Splits `multi_send_tx` into one transaction per denom, holding only that
+/// denom's input and output coins, in their original order. Every input or
+/// output holding the denom becomes one entry of its part, so a duplicate
+/// address or denom is still there for validation to reject. Burn and
+/// commission are worked out per denom, so running the parts separately gives
+/// the same changes as running the whole, merged per address and denom.
+pub fn split_by_denom(multi_send_tx: &MultiSend) -> BTreeMap<String, MultiSend> {
+    let mut parts: BTreeMap<String, MultiSend> = BTreeMap::new();
+    for (balances, is_input) in [(&multi_send_tx.inputs, true), (&multi_send_tx.outputs, false)] {
+        for balance in balances {
+            // Where this entry's coins of each denom went in their part.
+            let mut positions = Map::new();
+            for coin in &balance.coins {
+                let part = parts.entry(coin.denom.clone()).or_insert_with(|| MultiSend::new(Vec::new(), Vec::new()));
+                let side = if is_input { &mut part.inputs } else { &mut part.outputs };
+                let position = *positions.entry(coin.denom.as_str()).or_insert_with(|| {
+                    side.push(Balance { address: balance.address.clone(), coins: Vec::new() });
+                    side.len() - 1
+                });
+                side[position].coins.push(coin.clone());
+            }
+        }
+    }
+    parts
+}
+
+/// Counts the accounts, denoms and entries in `multi_send_tx` without
+/// validating it. Only addresses listed in the transaction are counted, so an
+/// issuer that just receives commission isn't, and neither is a burn address.
+pub fn stats(multi_send_tx: &MultiSend) -> TransactionStats {
+    let balances = || multi_send_tx.inputs.iter().chain(&multi_send_tx.outputs);
+    let addresses: Set<&str> = balances().map(|balance| balance.address.as_str()).collect();
+    let denoms: Set<&str> = balances().flat_map(|balance| &balance.coins).map(|coin| coin.denom.as_str()).collect();
+    TransactionStats {
+        affected_accounts: addresses.len(),
+        distinct_denoms: denoms.len(),
+        total_inputs: multi_send_tx.inputs.len(),
+        total_outputs: multi_send_tx.outputs.len(),
+    }
+}
+
+/// Applies `txs` to `ledger` in order and returns their combined balance
+/// changes, merged per address as in [`calculate_balance_changes`]. Each
+/// transaction sees the balances left by the ones before it. If any of them
+/// fails, its error is returned and the ledger is left exactly as it was.
+pub fn apply_batch(ledger: &mut Ledger, definitions: &[DenomDefinition], txs: &[MultiSend]) -> Result<Vec<Balance>, BalanceError> {
+    let mut working = ledger.clone();
+    let mut balance_changes = BalanceChanges::default();
+    for multi_send_tx in txs {
+        for balance in apply_to_ledger(&mut working, definitions, multi_send_tx)? {
+            for coin in balance.coins {
+                balance_changes.add(&balance.address, &coin.denom, coin.amount.get())?;
+            }
+        }
+    }
+    *ledger = working;
+    Ok(balance_changes.into_balances())
+}
+
+/// Applies the first `up_to` of `txs` to `initial` in order, as
+/// [`apply_batch`] would, and returns every balance afterwards: first the
+/// addresses and coins of `initial`, then the ones the transactions added, in
+/// the order they first appear in the combined changes. Coins left at zero are
+/// kept. `up_to` past the end replays every transaction, and 0 returns
+/// `initial` as validated.
+pub fn replay(initial: &[Balance], definitions: &[DenomDefinition], txs: &[MultiSend], up_to: usize) -> Result<Vec<Balance>, BalanceError> {
+    let mut ledger = Ledger::from_balances(initial)?;
+    let balance_changes = apply_batch(&mut ledger, definitions, &txs[..up_to.min(txs.len())])?;
+    let mut state = BalanceChanges::default();
+    for balance in initial.iter().chain(&balance_changes) {
+        for coin in &balance.coins {
+            *state.amount_mut(&balance.address, &coin.denom) = Amount::new(ledger.balance_of(&balance.address, &coin.denom));
+        }
+    }
+    Ok(state.balances)
+}
+
+// Outcome of running a transaction: the per-address changes and the fees
+// collected. The changes are applied to a ledger separately.
+struct Executed {
+    balance_changes: Vec<Balance>,
+    summary: TransferSummary,
+}
+
+fn execute(validated: Validated, multi_send_tx: &MultiSend, burn_address: Option<&str>, imbalance_sink: Option<&str>) -> Result<Executed, BalanceError> {
+    let Validated { definition_map, fee_shares, minted, mut imbalance, warnings, diagnostics } = validated;
+    let mut balance_changes = BalanceChanges::default();
+    for balance in &multi_send_tx.outputs {
+        for coin in &balance.coins {
+            balance_changes.add(&balance.address, &coin.denom, coin.amount.get())?;
+        }
+    }
+    let mut commissions = BalanceChanges::default();
+    let mut burns = BalanceChanges::default();
+    let mut sunk = BalanceChanges::default();
+    let mut summary = TransferSummary { warnings, diagnostics, ..TransferSummary::default() };
+    for (i, (balance, shares)) in multi_send_tx.inputs.iter().zip(fee_shares).enumerate() {
+        for (j, (coin, (total_burn_amount, total_commission_amount))) in balance.coins.iter().zip(shares).enumerate() {
+            // Issuer status is decided per coin: an issuer sends its own denom fee-free
+            // but pays burn and commission like anyone else on denoms it doesn't issue.
+            // Undefined denoms only get this far when allowed, and move fee-free too.
+            match definition_map.get(coin.denom.as_str()) {
+                Some(definition) if balance.address != definition.issuer => {
+                    let deduction = coin_add_checked(coin.amount.get(), total_burn_amount, &coin.denom)
+                        .and_then(|amount| coin_add_checked(amount, total_commission_amount, &coin.denom))?;
+                    balance_changes.add(&balance.address, &coin.denom, coin_sub_checked(0, deduction, &coin.denom)?)?;
+                    add_to_total(&mut summary.burned, &coin.denom, total_burn_amount)?;
+                    add_to_total(&mut summary.commission, &coin.denom, total_commission_amount)?;
+                    if total_commission_amount != 0 {
+                        commissions.add(&definition.issuer, &coin.denom, total_commission_amount)?;
+                    }
+                    if let Some(burn_address) = burn_address.filter(|_| total_burn_amount != 0) {
+                        burns.add(burn_address, &coin.denom, total_burn_amount)?;
+                    }
+                }
+                _ => {
+                    let minted = minted.get(&(i, j)).copied().unwrap_or(0);
+                    balance_changes.add(&balance.address, &coin.denom, coin_sub_checked(minted, coin.amount.get(), &coin.denom)?)?;
+                    if minted != 0 {
+                        add_to_total(&mut summary.minted, &coin.denom, minted)?;
+                    }
+                }
+            }
+            // Taken out of the map on its first input coin so a denom's remainder
+            // is only routed once, in input order.
+            if let Some(remainder) = imbalance.remove(coin.denom.as_str()) {
+                if let Some(imbalance_sink) = imbalance_sink {
+                    sunk.add(imbalance_sink, &coin.denom, remainder)?;
+                } else {
+                    add_to_total(&mut summary.burned, &coin.denom, remainder)?;
+                    if let Some(burn_address) = burn_address {
+                        burns.add(burn_address, &coin.denom, remainder)?;
+                    }
+                }
+            }
+        }
+    }
+    // Commission goes to the denom's issuer; the burn is removed from circulation
+    // unless there's a burn address to receive it.
+    for recipient in commissions.into_balances().into_iter().chain(burns.into_balances()).chain(sunk.into_balances()) {
+        for coin in recipient.coins {
+            balance_changes.add(&recipient.address, &coin.denom, coin.amount.get())?;
+        }
+    }
+    let balance_changes = balance_changes.into_balances();
+    for balance in &balance_changes {
+        for coin in &balance.coins {
+            if definition_map.get(coin.denom.as_str()).is_some_and(|definition| balance.address == definition.issuer) {
+                summary.issuers.insert((balance.address.clone(), coin.denom.clone()));
+            }
+        }
+    }
+    if cfg!(any(debug_assertions, feature = "debug_checks")) {
+        assert_conserved(&balance_changes, &summary, burn_address.is_some());
+    }
+    Ok(Executed { balance_changes, summary })
+}
+
+// Safety net for the arithmetic above: per denom, the changes must net to
+// what was minted less what was burned, or to what was minted when the burn
+// went to a burn address. Sums wrap, which is still exact here since the true
+// total fits in an i128 even when a partial sum doesn't.
+fn assert_conserved(balance_changes: &[Balance], summary: &TransferSummary, burn_credited: bool) {
+    let mut net: Map<&str, i128> = Map::new();
+    for coin in balance_changes.iter().flat_map(|balance| &balance.coins) {
+        let total = net.entry(&coin.denom).or_insert(0);
+        *total = total.wrapping_add(coin.amount.get());
+    }
+    for (denom, total) in net {
+        let minted = summary.minted.get(denom).copied().unwrap_or(0);
+        let burned = if burn_credited { 0 } else { summary.burned.get(denom).copied().unwrap_or(0) };
+        assert!(total == minted.wrapping_sub(burned), "balance changes for {} net to {}, but {} was minted and {} burned", denom, total, minted, burned);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The transfer from the challenge description: account1 and account2 send
+    // both denoms to account_recipient, with issuer_account as the issuer.
+    pub(crate) fn sample() -> (Vec<Balance>, Vec<DenomDefinition>, MultiSend) {
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 1000), ("denom2", 2000)]), ("account2", vec![("denom1", 500), ("denom2", 1500)])]);
+        let definitions = vec![DenomDefinition::with_bps("denom1", "issuer_account", 1000, 500), DenomDefinition::with_bps("denom2", "issuer_account", 2000, 1000)];
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom1", 300)
+            .input("account1", "denom2", 1000)
+            .input("account2", "denom1", 200)
+            .input("account2", "denom2", 500)
+            .output("account_recipient", "denom1", 500)
+            .output("account_recipient", "denom2", 1500)
+            .build();
+        (original_balances, definitions, multi_send_tx)
+    }
+
+    #[test]
+    fn test_balance_changes() {
+        // Test case setup
+        let original_balances = vec![
+            Balance {
+                address: "account1".to_string(),
+                coins: vec![
+                    Coin { denom: "denom1".to_string(), amount: Amount::new(1000) },
+                    Coin { denom: "denom2".to_string(), amount: Amount::new(2000) },
+                ],
+            },
+            Balance {
+                address: "account2".to_string(),
+                coins: vec![
+                    Coin { denom: "denom1".to_string(), amount: Amount::new(500) },
+                    Coin { denom: "denom2".to_string(), amount: Amount::new(1500) },
+                ],
+            },
+        ];
+
+        let definitions = vec![
+            DenomDefinition {
+                denom: "denom1".to_string(),
+                issuer: "issuer_account".to_string(),
+                burn_rate: Rate::from_bps(1000),
+                commission_rate: Rate::from_bps(500),
+                max_burn: None,
+                max_commission: None,
+                precision: None,
+                fee_exempt: vec![],
+            },
+            DenomDefinition {
+                denom: "denom2".to_string(),
+                issuer: "issuer_account".to_string(),
+                burn_rate: Rate::from_bps(2000),
+                commission_rate: Rate::from_bps(1000),
+                max_burn: None,
+                max_commission: None,
+                precision: None,
+                fee_exempt: vec![],
+            },
+        ];
+
+        let multi_send_tx = MultiSend {
+            inputs: vec![
+                Balance {
+                    address: "account1".to_string(),
+                    coins: vec![
+                        Coin { denom: "denom1".to_string(), amount: Amount::new(300) },
+                        Coin { denom: "denom2".to_string(), amount: Amount::new(1000) },
+                    ],
+                },
+                Balance {
+                    address: "account2".to_string(),
+                    coins: vec![
+                        Coin { denom: "denom1".to_string(), amount: Amount::new(200) },
+                        Coin { denom: "denom2".to_string(), amount: Amount::new(500) },
+                    ],
+                },
+            ],
+            outputs: vec![
+                Balance {
+                    address: "account_recipient".to_string(),
+                    coins: vec![
+                        Coin { denom: "denom1".to_string(), amount: Amount::new(450) },
+                        Coin { denom: "denom2".to_string(), amount: Amount::new(1400) },
+                    ],
+                },
+                Balance {
+                    address: "issuer_account".to_string(),
+                    coins: vec![
+                        Coin { denom: "denom1".to_string(), amount: Amount::new(50) },
+                        Coin { denom: "denom2".to_string(), amount: Amount::new(100) },
+                    ],
+                },
+            ],
+        };
+
+        // Expected balance changes
+        let expected_balance_changes = vec![
+            Balance {
+                address: "account_recipient".to_string(),
+                coins: vec![
+                    Coin { denom: "denom1".to_string(), amount: Amount::new(450) },
+                    Coin { denom: "denom2".to_string(), amount: Amount::new(1400) },
+                ],
+            },
+            Balance {
+                address: "issuer_account".to_string(),
+                coins: vec![
+                    Coin { denom: "denom1".to_string(), amount: Amount::new(73) },
+                    Coin { denom: "denom2".to_string(), amount: Amount::new(240) },
+                ],
+            },
+            Balance {
+                address: "account1".to_string(),
+                coins: vec![
+                    Coin { denom: "denom1".to_string(), amount: Amount::new(-341) },
+                    Coin { denom: "denom2".to_string(), amount: Amount::new(-1280) },
+                ],
+            },
+            Balance {
+                address: "account2".to_string(),
+                coins: vec![
+                    Coin { denom: "denom1".to_string(), amount: Amount::new(-227) },
+                    Coin { denom: "denom2".to_string(), amount: Amount::new(-640) },
+                ],
+            },
+        ];
+
+        // Calculate balance changes
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+
+        // Compare with expected results
+        assert_eq!(balance_changes, Ok(expected_balance_changes));
+    }
+
+    #[test]
+    fn test_balance_error_display() {
+        let insufficient = BalanceError::InsufficientBalance {
+            address: "account1".to_string(),
+            denom: "denom1".to_string(),
+            have: 100,
+            need: 300,
+        };
+        assert_eq!(insufficient.to_string(), "account1 does not have enough balance for denom1");
+
+        let missing = BalanceError::MissingDefinition { denom: "denom3".to_string() };
+        assert_eq!(missing.to_string(), "Denomination denom3 does not have a definition");
+
+        let mismatch = BalanceError::InputOutputMismatch {
+            denom: "denom1".to_string(),
+            input_total: 500,
+            output_total: 550,
+        };
+        assert_eq!(mismatch.to_string(), "Input and output does not match for denom1: inputs total 500, outputs total 550, difference 50");
+    }
+
+    #[test]
+    fn test_constructors_and_accessors() {
+        let balance = Balance::new("account1", vec![Coin::new("denom1", 1000), Coin::new("denom2", -2000)]);
+        assert_eq!(balance.address(), "account1");
+        assert_eq!(balance.coins().len(), 2);
+        assert_eq!(balance.coins()[0].denom(), "denom1");
+        assert_eq!(balance.coins()[0].amount(), Amount::new(1000));
+        assert_eq!(balance.coins()[1].amount(), Amount::new(-2000));
+        assert_eq!(
+            balance,
+            Balance {
+                address: "account1".to_string(),
+                coins: vec![
+                    Coin { denom: "denom1".to_string(), amount: Amount::new(1000) },
+                    Coin { denom: "denom2".to_string(), amount: Amount::new(-2000) },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_input_sum_overflow() {
+        let original_balances = vec![
+            Balance::new("account1", vec![Coin::new("denom1", i128::MAX)]),
+            Balance::new("account2", vec![Coin::new("denom1", i128::MAX)]),
+        ];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::ZERO, Rate::ZERO)];
+        let multi_send_tx = MultiSend::new(
+            vec![
+                Balance::new("account1", vec![Coin::new("denom1", i128::MAX)]),
+                Balance::new("account2", vec![Coin::new("denom1", 1)]),
+            ],
+            vec![Balance::new("account_recipient", vec![Coin::new("denom1", i128::MAX)])],
+        );
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+
+        assert_eq!(balance_changes, Err(BalanceError::Overflow { denom: "denom1".to_string() }));
+    }
+
+    #[test]
+    fn test_output_sum_overflow() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", i128::MAX)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::ZERO, Rate::ZERO)];
+        let multi_send_tx = MultiSend::new(
+            vec![Balance::new("account1", vec![Coin::new("denom1", i128::MAX)])],
+            vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", i128::MAX)]),
+                Balance::new("account2", vec![Coin::new("denom1", i128::MAX)]),
+            ],
+        );
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+
+        assert_eq!(balance_changes, Err(BalanceError::Overflow { denom: "denom1".to_string() }));
+    }
+
+    #[test]
+    fn test_negative_input_rejected() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        let multi_send_tx = MultiSend::new(
+            vec![Balance::new("account1", vec![Coin::new("denom1", -100)])],
+            vec![Balance::new("account_recipient", vec![Coin::new("denom1", -100)])],
+        );
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+
+        assert_eq!(
+            balance_changes,
+            Err(BalanceError::NegativeAmount {
+                address: "account1".to_string(),
+                denom: "denom1".to_string(),
+                amount: -100,
+            })
+        );
+    }
+
+    #[test]
+    fn test_fixed_point_rates_are_exact() {
+        // With f64 rates 30 * 0.1 came out as 3.0000000000000004 and ceiled to a burn of 4.
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", "0.1".parse().unwrap(), "0.05".parse().unwrap())];
+        let multi_send_tx = MultiSend::new(
+            vec![Balance::new("account1", vec![Coin::new("denom1", 30)])],
+            vec![Balance::new("account_recipient", vec![Coin::new("denom1", 30)])],
+        );
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+
+        // 30 base + 3 burn + ceil(1.5) commission
+        assert_eq!(
+            balance_changes,
+            Ok(vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 30)]),
+                Balance::new("account1", vec![Coin::new("denom1", -35)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 2)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_result_ordering() {
+        let original_balances = vec![
+            Balance::new("account2", vec![Coin::new("denom1", 1000), Coin::new("denom2", 1000)]),
+            Balance::new("account1", vec![Coin::new("denom1", 1000), Coin::new("denom2", 1000)]),
+        ];
+        let definitions = vec![
+            DenomDefinition::new("denom1", "issuer_account", Rate::ZERO, Rate::ZERO),
+            DenomDefinition::new("denom2", "issuer_account", Rate::ZERO, Rate::ZERO),
+        ];
+        let multi_send_tx = MultiSend::new(
+            vec![
+                Balance::new("account2", vec![Coin::new("denom2", 20), Coin::new("denom1", 10)]),
+                Balance::new("account1", vec![Coin::new("denom1", 30)]),
+            ],
+            vec![
+                Balance::new("recipient_b", vec![Coin::new("denom2", 20)]),
+                Balance::new("recipient_a", vec![Coin::new("denom1", 40)]),
+            ],
+        );
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+
+        assert_eq!(
+            balance_changes,
+            Ok(vec![
+                Balance::new("recipient_b", vec![Coin::new("denom2", 20)]),
+                Balance::new("recipient_a", vec![Coin::new("denom1", 40)]),
+                Balance::new("account2", vec![Coin::new("denom2", -20), Coin::new("denom1", -10)]),
+                Balance::new("account1", vec![Coin::new("denom1", -30)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_sender_and_receiver_merged() {
+        let original_balances = vec![
+            Balance::new("account1", vec![Coin::new("denom1", 1000)]),
+            Balance::new("issuer_account", vec![Coin::new("denom2", 1000)]),
+        ];
+        let definitions = vec![
+            DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500)),
+            DenomDefinition::new("denom2", "issuer2", Rate::from_bps(1000), Rate::ZERO),
+        ];
+        let multi_send_tx = MultiSend::new(
+            vec![
+                Balance::new("account1", vec![Coin::new("denom1", 100)]),
+                Balance::new("issuer_account", vec![Coin::new("denom2", 200)]),
+            ],
+            vec![
+                Balance::new("issuer_account", vec![Coin::new("denom1", 100)]),
+                Balance::new("account1", vec![Coin::new("denom2", 200)]),
+            ],
+        );
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+
+        assert_eq!(
+            balance_changes,
+            Ok(vec![
+                Balance::new("issuer_account", vec![Coin::new("denom1", 100), Coin::new("denom2", -220)]),
+                Balance::new("account1", vec![Coin::new("denom2", 200), Coin::new("denom1", -100)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_commission_credited_to_issuer() {
+        let original_balances = vec![
+            Balance::new("account1", vec![Coin::new("denom1", 1000)]),
+            Balance::new("account2", vec![Coin::new("denom1", 1000)]),
+            Balance::new("issuer_account", vec![Coin::new("denom1", 10)]),
+        ];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        let multi_send_tx = MultiSend::new(
+            vec![
+                Balance::new("account1", vec![Coin::new("denom1", 400)]),
+                Balance::new("account2", vec![Coin::new("denom1", 600)]),
+            ],
+            vec![Balance::new("account_recipient", vec![Coin::new("denom1", 1000)])],
+        );
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap();
+
+        // 5% commission on 400 and 600; the 10% burn goes nowhere
+        assert_eq!(
+            balance_changes,
+            vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 1000)]),
+                Balance::new("account1", vec![Coin::new("denom1", -460)]),
+                Balance::new("account2", vec![Coin::new("denom1", -690)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 50)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transfer_summary() {
+        let original_balances = vec![
+            Balance::new("account1", vec![Coin::new("denom1", 1000), Coin::new("denom2", 1000)]),
+            Balance::new("account2", vec![Coin::new("denom1", 1000)]),
+        ];
+        let definitions = vec![
+            DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500)),
+            DenomDefinition::new("denom2", "issuer_account", Rate::from_bps(2000), Rate::ZERO),
+        ];
+        let multi_send_tx = MultiSend::new(
+            vec![
+                Balance::new("account1", vec![Coin::new("denom1", 15), Coin::new("denom2", 100)]),
+                Balance::new("account2", vec![Coin::new("denom1", 15)]),
+            ],
+            vec![Balance::new("account_recipient", vec![Coin::new("denom1", 30), Coin::new("denom2", 100)])],
+        );
+
+        let (_, summary) = calculate_balance_changes_with_summary(&original_balances, &definitions, &multi_send_tx).unwrap();
+
+        // denom1: burn of 3 and commission of 1.5, rounded up to 2, split over two equal senders
+        assert_eq!(summary.burned, BTreeMap::from([("denom1".to_string(), 3), ("denom2".to_string(), 20)]));
+        assert_eq!(summary.commission, BTreeMap::from([("denom1".to_string(), 2), ("denom2".to_string(), 0)]));
+    }
+
+    #[test]
+    fn test_output_without_input_rejected() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::ZERO, Rate::ZERO)];
+        let multi_send_tx = MultiSend::new(
+            vec![Balance::new("account1", vec![Coin::new("denom1", 100)])],
+            vec![Balance::new("account_recipient", vec![Coin::new("denom1", 100), Coin::new("denom3", 50)])],
+        );
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+
+        assert_eq!(
+            balance_changes,
+            Err(BalanceError::InputOutputMismatch { denom: "denom3".to_string(), input_total: 0, output_total: 50 })
+        );
+    }
+
+    #[test]
+    fn test_issuer_only_denom_passes_match_check() {
+        let original_balances = vec![Balance::new("issuer_account", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        let multi_send_tx = MultiSend::new(
+            vec![Balance::new("issuer_account", vec![Coin::new("denom1", 100)])],
+            vec![Balance::new("account_recipient", vec![Coin::new("denom1", 100)])],
+        );
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+
+        assert_eq!(
+            balance_changes,
+            Ok(vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 100)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", -100)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_duplicate_denom_in_original_balance_rejected() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000), Coin::new("denom1", 500)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::ZERO, Rate::ZERO)];
+        let multi_send_tx = MultiSend::new(
+            vec![Balance::new("account1", vec![Coin::new("denom1", 100)])],
+            vec![Balance::new("account_recipient", vec![Coin::new("denom1", 100)])],
+        );
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+
+        assert_eq!(
+            balance_changes,
+            Err(BalanceError::DuplicateDenom { address: "account1".to_string(), denom: "denom1".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_duplicate_denom_in_input_rejected() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::ZERO, Rate::ZERO)];
+        let multi_send_tx = MultiSend::new(
+            vec![Balance::new("account1", vec![Coin::new("denom1", 100), Coin::new("denom1", 100)])],
+            vec![Balance::new("account_recipient", vec![Coin::new("denom1", 200)])],
+        );
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+
+        assert_eq!(
+            balance_changes,
+            Err(BalanceError::DuplicateDenom { address: "account1".to_string(), denom: "denom1".to_string() })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_multi_send_from_json() {
+        let original_balances: Vec<Balance> = serde_json::from_str(
+            r#"[{"address": "account1", "coins": [{"denom": "denom1", "amount": "1000"}]}]"#,
+        )
+        .unwrap();
+        let definitions: Vec<DenomDefinition> = serde_json::from_str(
+            r#"[{"denom": "denom1", "issuer": "issuer_account", "burn_rate": "0.1", "commission_rate": "0.05"}]"#,
+        )
+        .unwrap();
+        let json = r#"{
+            "inputs": [{"address": "account1", "coins": [{"denom": "denom1", "amount": "30"}]}],
+            "outputs": [{"address": "account_recipient", "coins": [{"denom": "denom1", "amount": "30"}]}]
+        }"#;
+        let multi_send_tx: MultiSend = serde_json::from_str(json).unwrap();
+
+        let round_tripped: MultiSend = serde_json::from_str(&serde_json::to_string(&multi_send_tx).unwrap()).unwrap();
+        assert_eq!(round_tripped.inputs, multi_send_tx.inputs);
+        assert_eq!(round_tripped.outputs, multi_send_tx.outputs);
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &round_tripped).unwrap();
+        assert_eq!(
+            serde_json::to_value(&balance_changes).unwrap(),
+            serde_json::json!([
+                {"address": "account_recipient", "coins": [{"denom": "denom1", "amount": "30"}]},
+                {"address": "account1", "coins": [{"denom": "denom1", "amount": "-35"}]},
+                {"address": "issuer_account", "coins": [{"denom": "denom1", "amount": "2"}]}
+            ])
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_amounts_serialize_as_strings() {
+        let coin = Coin::new("denom1", i128::MAX);
+        let json = serde_json::to_string(&coin).unwrap();
+        assert_eq!(json, format!(r#"{{"denom":"denom1","amount":"{}"}}"#, i128::MAX));
+        assert_eq!(serde_json::from_str::<Coin>(&json).unwrap(), coin);
+        assert!(serde_json::from_str::<Coin>(r#"{"denom":"denom1","amount":1000}"#).is_err());
+        assert!(serde_json::from_str::<Coin>(r#"{"denom":"denom1","amount":"+5"}"#).is_err());
+
+        // Balance changes are negative, and read back as written.
+        let coin = Coin::new("denom1", -345);
+        assert_eq!(serde_json::from_str::<Coin>(&serde_json::to_string(&coin).unwrap()).unwrap(), coin);
+    }
+
+    #[test]
+    fn test_issuers_pay_fees_on_other_denoms() {
+        let original_balances = vec![
+            Balance::new("issuer_a", vec![Coin::new("denom1", 1000), Coin::new("denom2", 1000)]),
+            Balance::new("issuer_b", vec![Coin::new("denom1", 1000), Coin::new("denom2", 1000)]),
+        ];
+        let definitions = vec![
+            DenomDefinition::new("denom1", "issuer_a", Rate::from_bps(1000), Rate::from_bps(500)),
+            DenomDefinition::new("denom2", "issuer_b", Rate::from_bps(2000), Rate::from_bps(1000)),
+        ];
+        let multi_send_tx = MultiSend::new(
+            vec![
+                Balance::new("issuer_a", vec![Coin::new("denom1", 100), Coin::new("denom2", 100)]),
+                Balance::new("issuer_b", vec![Coin::new("denom2", 200), Coin::new("denom1", 200)]),
+            ],
+            vec![Balance::new("account_recipient", vec![Coin::new("denom1", 300), Coin::new("denom2", 300)])],
+        );
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+
+        // Each issuer sends its own denom fee-free, pays 20 burn + 10 commission on the
+        // other one and collects that commission from the other issuer.
+        assert_eq!(
+            balance_changes,
+            Ok(vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 300), Coin::new("denom2", 300)]),
+                Balance::new("issuer_a", vec![Coin::new("denom1", -90), Coin::new("denom2", -130)]),
+                Balance::new("issuer_b", vec![Coin::new("denom2", -190), Coin::new("denom1", -230)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_fee_shares_add_up_to_total() {
+        let original_balances = vec![
+            Balance::new("account1", vec![Coin::new("denom1", 1000)]),
+            Balance::new("account2", vec![Coin::new("denom1", 1000)]),
+            Balance::new("account3", vec![Coin::new("denom1", 1000)]),
+        ];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::ZERO)];
+        let multi_send_tx = MultiSend::new(
+            vec![
+                Balance::new("account1", vec![Coin::new("denom1", 7)]),
+                Balance::new("account2", vec![Coin::new("denom1", 11)]),
+                Balance::new("account3", vec![Coin::new("denom1", 13)]),
+            ],
+            vec![Balance::new("account_recipient", vec![Coin::new("denom1", 31)])],
+        );
+
+        let (balance_changes, summary) = calculate_balance_changes_with_summary(&original_balances, &definitions, &multi_send_tx).unwrap();
+
+        // The burn of 3.1 rounds up to 4. Exact shares are 0.90, 1.42 and 1.68; flooring
+        // leaves 2 units, which go to the two largest remainders. Ceiling each share on
+        // its own would have burned 1 + 2 + 2 = 5.
+        assert_eq!(summary.burned["denom1"], 4);
+        assert_eq!(
+            balance_changes,
+            vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 31)]),
+                Balance::new("account1", vec![Coin::new("denom1", -8)]),
+                Balance::new("account2", vec![Coin::new("denom1", -12)]),
+                Balance::new("account3", vec![Coin::new("denom1", -15)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_multi_send() {
+        let original_balances = || vec![Balance::new("account1", vec![Coin::new("denom1", 1000), Coin::new("denom2", 1000)])];
+        let definitions = || vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        let transfer = |inputs: Vec<Coin>, outputs: Vec<Coin>| {
+            MultiSend::new(vec![Balance::new("account1", inputs)], vec![Balance::new("account_recipient", outputs)])
+        };
+
+        assert_eq!(
+            validate_multi_send(&original_balances(), &definitions(), &transfer(vec![Coin::new("denom1", 100)], vec![Coin::new("denom1", 100)])),
+            Ok(())
+        );
+        assert_eq!(
+            validate_multi_send(&original_balances(), &definitions(), &transfer(vec![Coin::new("denom1", -1)], vec![])),
+            Err(BalanceError::NegativeAmount { address: "account1".to_string(), denom: "denom1".to_string(), amount: -1 })
+        );
+        assert_eq!(
+            validate_multi_send(
+                &original_balances(),
+                &definitions(),
+                &transfer(vec![Coin::new("denom1", 1), Coin::new("denom1", 1)], vec![Coin::new("denom1", 2)])
+            ),
+            Err(BalanceError::DuplicateDenom { address: "account1".to_string(), denom: "denom1".to_string() })
+        );
+        assert_eq!(
+            validate_multi_send(&original_balances(), &definitions(), &transfer(vec![Coin::new("denom2", 100)], vec![Coin::new("denom2", 100)])),
+            Err(BalanceError::MissingDefinition { denom: "denom2".to_string() })
+        );
+        assert_eq!(
+            validate_multi_send(&original_balances(), &definitions(), &transfer(vec![Coin::new("denom1", 2000)], vec![Coin::new("denom1", 2000)])),
+            Err(BalanceError::InsufficientBalance {
+                address: "account1".to_string(),
+                denom: "denom1".to_string(),
+                have: 1000,
+                need: 2000,
+            })
+        );
+        assert_eq!(
+            validate_multi_send(&original_balances(), &definitions(), &transfer(vec![Coin::new("denom1", 100)], vec![Coin::new("denom1", 90)])),
+            Err(BalanceError::InputOutputMismatch { denom: "denom1".to_string(), input_total: 100, output_total: 90 })
+        );
+        assert_eq!(
+            validate_multi_send(
+                &original_balances(),
+                &definitions(),
+                &transfer(vec![Coin::new("denom1", 100)], vec![Coin::new("denom1", 100), Coin::new("denom3", 1)])
+            ),
+            Err(BalanceError::InputOutputMismatch { denom: "denom3".to_string(), input_total: 0, output_total: 1 })
+        );
+    }
+
+    #[test]
+    fn test_fees_count_towards_insufficient_balance() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 100)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        let multi_send_tx = MultiSend::new(
+            vec![Balance::new("account1", vec![Coin::new("denom1", 100)])],
+            vec![Balance::new("recipient", vec![Coin::new("denom1", 100)])],
+        );
+
+        // 100 covers the amount sent, but not the 10 burned and 5 commission.
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Err(BalanceError::InsufficientBalance {
+                address: "account1".to_string(),
+                denom: "denom1".to_string(),
+                have: 100,
+                need: 115,
+            })
+        );
+    }
+
+    #[test]
+    fn test_fee_base_is_min_of_non_issuer_inputs_and_outputs() {
+        let original_balances = vec![
+            Balance::new("account1", vec![Coin::new("denom1", 1000)]),
+            Balance::new("issuer_account", vec![Coin::new("denom1", 1000)]),
+        ];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+
+        // The issuer tops up the transfer: 120 reaches non-issuers but only
+        // 100 came from them, so the base is 100.
+        let multi_send_tx = MultiSend::new(
+            vec![
+                Balance::new("account1", vec![Coin::new("denom1", 100)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 50)]),
+            ],
+            vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 120)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 30)]),
+            ],
+        );
+        let (_, summary) = calculate_balance_changes_with_summary(&original_balances, &definitions, &multi_send_tx).unwrap();
+        assert_eq!(summary.burned["denom1"], 10);
+        assert_eq!(summary.commission["denom1"], 5);
+
+        // 40 of the 100 sent goes back to the issuer, so only 60 is charged.
+        let multi_send_tx = MultiSend::new(
+            vec![Balance::new("account1", vec![Coin::new("denom1", 100)])],
+            vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 60)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 40)]),
+            ],
+        );
+        assert_eq!(
+            calculate_balance_changes_with_summary(&original_balances, &definitions, &multi_send_tx),
+            Ok((
+                vec![
+                    Balance::new("account_recipient", vec![Coin::new("denom1", 60)]),
+                    Balance::new("issuer_account", vec![Coin::new("denom1", 43)]),
+                    Balance::new("account1", vec![Coin::new("denom1", -109)]),
+                ],
+                TransferSummary {
+                    burned: BTreeMap::from([("denom1".to_string(), 6)]),
+                    commission: BTreeMap::from([("denom1".to_string(), 3)]),
+                    issuers: BTreeSet::from([("issuer_account".to_string(), "denom1".to_string())]),
+                    ..TransferSummary::default()
+                },
+            ))
+        );
+    }
+
+    #[test]
+    fn test_multi_send_builder() {
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom1", 300)
+            .input("account2", "denom1", 200)
+            .input("account1", "denom2", 1000)
+            .input("account2", "denom2", 500)
+            .output("account_recipient", "denom1", 450)
+            .output("account_recipient", "denom2", 1400)
+            .output("issuer_account", "denom1", 50)
+            .output("issuer_account", "denom2", 100)
+            .build();
+
+        assert_eq!(
+            multi_send_tx,
+            MultiSend::new(
+                vec![
+                    Balance::new("account1", vec![Coin::new("denom1", 300), Coin::new("denom2", 1000)]),
+                    Balance::new("account2", vec![Coin::new("denom1", 200), Coin::new("denom2", 500)]),
+                ],
+                vec![
+                    Balance::new("account_recipient", vec![Coin::new("denom1", 450), Coin::new("denom2", 1400)]),
+                    Balance::new("issuer_account", vec![Coin::new("denom1", 50), Coin::new("denom2", 100)]),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_rates_outside_unit_interval_rejected() {
+        let rate = |s: &str| s.parse::<Rate>().unwrap();
+        for (burn_rate, commission_rate) in [("0", "0"), ("1", "1"), ("0", "1"), ("0.999999999999999999", "0.000000000000000001")] {
+            assert_eq!(DenomDefinition::new("denom1", "issuer_account", rate(burn_rate), rate(commission_rate)).validate(), Ok(()));
+        }
+        for (burn_rate, commission_rate, invalid) in [
+            ("1.5", "0", "1.5"),
+            ("-0.1", "0", "-0.1"),
+            ("0", "1.000000000000000001", "1.000000000000000001"),
+            ("0", "-0.000000000000000001", "-0.000000000000000001"),
+        ] {
+            assert_eq!(
+                DenomDefinition::new("denom1", "issuer_account", rate(burn_rate), rate(commission_rate)).validate(),
+                Err(BalanceError::InvalidRate { denom: "denom1".to_string(), rate: rate(invalid) })
+            );
+        }
+
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", rate("1.5"), Rate::ZERO)];
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 100).output("recipient", "denom1", 100).build();
+        let error = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap_err();
+        assert_eq!(error.to_string(), "Denomination denom1 has rate 1.5 outside [0, 1]");
+    }
+
+    #[test]
+    fn test_empty_multi_send() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+
+        assert_eq!(calculate_balance_changes(&original_balances, &definitions, &MultiSend::new(vec![], vec![])), Ok(vec![]));
+        assert_eq!(calculate_balance_changes(&[], &[], &MultiSend::new(vec![], vec![])), Ok(vec![]));
+
+        let inputs_only = MultiSendBuilder::new().input("account1", "denom1", 100).build();
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &inputs_only),
+            Err(BalanceError::InputOutputMismatch { denom: "denom1".to_string(), input_total: 100, output_total: 0 })
+        );
+    }
+
+    #[test]
+    fn test_unknown_sender_rejected() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![
+            DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500)),
+            DenomDefinition::new("denom2", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500)),
+        ];
+
+        let multi_send_tx = MultiSendBuilder::new().input("stranger", "denom1", 100).output("recipient", "denom1", 100).build();
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Err(BalanceError::UnknownSender { address: "stranger".to_string() })
+        );
+
+        // A known sender without the denom is short rather than unknown.
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom2", 100).output("recipient", "denom2", 100).build();
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Err(BalanceError::InsufficientBalance {
+                address: "account1".to_string(),
+                denom: "denom2".to_string(),
+                have: 0,
+                need: 100,
+            })
+        );
+    }
+
+    #[test]
+    fn test_apply_multi_send() {
+        let (original_balances, definitions, _) = sample();
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom1", 300)
+            .input("account1", "denom2", 1000)
+            .input("account2", "denom1", 200)
+            .input("account2", "denom2", 500)
+            .output("account_recipient", "denom1", 450)
+            .output("account_recipient", "denom2", 1400)
+            .output("issuer_account", "denom1", 50)
+            .output("issuer_account", "denom2", 100)
+            .build();
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap();
+        let final_balances = apply_multi_send(&original_balances, &definitions, &multi_send_tx).unwrap();
+
+        assert_eq!(
+            final_balances,
+            vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 450), Coin::new("denom2", 1400)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 73), Coin::new("denom2", 240)]),
+                Balance::new("account1", vec![Coin::new("denom1", 659), Coin::new("denom2", 720)]),
+                Balance::new("account2", vec![Coin::new("denom1", 273), Coin::new("denom2", 860)]),
+            ]
+        );
+        // Each final amount is the original, or zero for a new account, plus the change.
+        for (change, final_balance) in balance_changes.iter().zip(&final_balances) {
+            assert_eq!(change.address, final_balance.address);
+            let original = original_balances.iter().find(|balance| balance.address == change.address);
+            for (delta, coin) in change.coins.iter().zip(&final_balance.coins) {
+                let before = original.and_then(|balance| balance.coins.iter().find(|c| c.denom == coin.denom)).map_or(Amount::ZERO, |c| c.amount);
+                assert_eq!(before + delta.amount, Ok(coin.amount));
+            }
+        }
+    }
+
+    #[test]
+    fn test_zero_rate_denom_deducts_exact_amount() {
+        let original_balances = vec![
+            Balance::new("account1", vec![Coin::new("denom1", 1000)]),
+            Balance::new("account2", vec![Coin::new("denom1", 1000)]),
+        ];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::ZERO, Rate::ZERO)];
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom1", 333)
+            .input("account2", "denom1", 1)
+            .output("account_recipient", "denom1", 334)
+            .build();
+
+        assert_eq!(
+            calculate_balance_changes_with_summary(&original_balances, &definitions, &multi_send_tx),
+            Ok((
+                vec![
+                    Balance::new("account_recipient", vec![Coin::new("denom1", 334)]),
+                    Balance::new("account1", vec![Coin::new("denom1", -333)]),
+                    Balance::new("account2", vec![Coin::new("denom1", -1)]),
+                ],
+                TransferSummary {
+                    burned: BTreeMap::from([("denom1".to_string(), 0)]),
+                    commission: BTreeMap::from([("denom1".to_string(), 0)]),
+                    ..TransferSummary::default()
+                },
+            ))
+        );
+    }
+
+    #[test]
+    fn test_collecting_reports_every_error() {
+        let original_balances = vec![
+            Balance::new("account1", vec![Coin::new("denom1", 100)]),
+            Balance::new("account2", vec![Coin::new("denom2", 1000)]),
+        ];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::ZERO, Rate::ZERO)];
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom1", 300)
+            .input("account2", "denom2", 500)
+            .output("account_recipient", "denom1", 300)
+            .output("account_recipient", "denom2", 500)
+            .build();
+
+        assert_eq!(
+            calculate_balance_changes_collecting(&original_balances, &definitions, &multi_send_tx),
+            Err(vec![
+                BalanceError::InsufficientBalance {
+                    address: "account1".to_string(),
+                    denom: "denom1".to_string(),
+                    have: 100,
+                    need: 300,
+                },
+                BalanceError::MissingDefinition { denom: "denom2".to_string() },
+            ])
+        );
+        // The fail-fast variant stops at the first of them.
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Err(BalanceError::InsufficientBalance {
+                address: "account1".to_string(),
+                denom: "denom1".to_string(),
+                have: 100,
+                need: 300,
+            })
+        );
+    }
+
+    #[test]
+    fn test_ledger_applies_transactions_in_sequence() {
+        let mut ledger = Ledger::from_balances(&[Balance::new("account1", vec![Coin::new("denom1", 1000)])]).unwrap();
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+
+        let first = MultiSendBuilder::new().input("account1", "denom1", 400).output("account2", "denom1", 400).build();
+        assert_eq!(
+            apply_to_ledger(&mut ledger, &definitions, &first),
+            Ok(vec![
+                Balance::new("account2", vec![Coin::new("denom1", 400)]),
+                Balance::new("account1", vec![Coin::new("denom1", -460)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 20)]),
+            ])
+        );
+        assert_eq!(ledger.balance_of("account1", "denom1"), 540);
+
+        // account2 only exists because the first transaction paid it.
+        let second = MultiSendBuilder::new().input("account2", "denom1", 200).output("account1", "denom1", 200).build();
+        apply_to_ledger(&mut ledger, &definitions, &second).unwrap();
+        assert_eq!(ledger.balance_of("account1", "denom1"), 740);
+        assert_eq!(ledger.balance_of("account2", "denom1"), 170);
+        assert_eq!(ledger.balance_of("issuer_account", "denom1"), 30);
+
+        // A rejected transaction leaves the ledger untouched.
+        let before = ledger.clone();
+        let third = MultiSendBuilder::new().input("account2", "denom1", 170).output("account1", "denom1", 170).build();
+        assert!(matches!(apply_to_ledger(&mut ledger, &definitions, &third), Err(BalanceError::InsufficientBalance { .. })));
+        assert_eq!(ledger, before);
+    }
+
+    #[test]
+    fn test_apply_batch_is_atomic() {
+        let original = Ledger::from_balances(&[Balance::new("account1", vec![Coin::new("denom1", 1000)])]).unwrap();
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        let txs = vec![
+            MultiSendBuilder::new().input("account1", "denom1", 400).output("account2", "denom1", 400).build(),
+            // account2 only has 400, not 500 plus fees.
+            MultiSendBuilder::new().input("account2", "denom1", 500).output("account1", "denom1", 500).build(),
+            MultiSendBuilder::new().input("account1", "denom1", 100).output("account2", "denom1", 100).build(),
+        ];
+
+        let mut ledger = original.clone();
+        assert!(matches!(apply_batch(&mut ledger, &definitions, &txs), Err(BalanceError::InsufficientBalance { .. })));
+        assert_eq!(ledger, original);
+
+        let valid = vec![txs[0].clone(), txs[2].clone()];
+        assert_eq!(
+            apply_batch(&mut ledger, &definitions, &valid),
+            Ok(vec![
+                Balance::new("account2", vec![Coin::new("denom1", 500)]),
+                Balance::new("account1", vec![Coin::new("denom1", -575)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 25)]),
+            ])
+        );
+        assert_eq!(ledger.balance_of("account1", "denom1"), 425);
+    }
+
+    #[test]
+    fn test_outputs_to_issuer_are_not_charged() {
+        let original_balances = vec![
+            Balance::new("account1", vec![Coin::new("denom1", 1000)]),
+            Balance::new("account2", vec![Coin::new("denom1", 1000)]),
+        ];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom1", 600)
+            .input("account2", "denom1", 400)
+            .output("account_recipient", "denom1", 700)
+            .output("issuer_account", "denom1", 300)
+            .build();
+
+        // The base is the 700 reaching a non-issuer, not the 1000 sent: a burn
+        // of 70 split 42/28 and a commission of 35 split 21/14.
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Ok(vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 700)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 335)]),
+                Balance::new("account1", vec![Coin::new("denom1", -663)]),
+                Balance::new("account2", vec![Coin::new("denom1", -442)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_calculate_deductions() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+
+        let deductions = calculate_deductions(&original_balances, &definitions, &multi_send_tx).unwrap();
+        let detail = |address: &str, denom: &str, base, burn, commission| DeductionDetail {
+            address: address.to_string(),
+            denom: denom.to_string(),
+            base,
+            burn,
+            commission,
+        };
+        assert_eq!(
+            deductions,
+            vec![
+                detail("account1", "denom1", 300, 30, 15),
+                detail("account1", "denom2", 1000, 200, 100),
+                detail("account2", "denom1", 200, 20, 10),
+                detail("account2", "denom2", 500, 100, 50),
+            ]
+        );
+
+        // Each detail adds up to what the sender actually loses.
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap();
+        for deduction in &deductions {
+            let change = balance_changes
+                .iter()
+                .find(|balance| balance.address == deduction.address)
+                .and_then(|balance| balance.coins.iter().find(|coin| coin.denom == deduction.denom))
+                .unwrap();
+            assert_eq!(change.amount.get(), -(deduction.base + deduction.burn + deduction.commission));
+        }
+    }
+
+    #[test]
+    fn test_empty_or_padded_identifiers_rejected() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::ZERO, Rate::ZERO)];
+        let invalid_denom = |denom: &str| Err(BalanceError::InvalidDenom { denom: denom.to_string() });
+
+        let empty_input = MultiSendBuilder::new().input("account1", "", 100).output("recipient", "denom1", 100).build();
+        assert_eq!(calculate_balance_changes(&original_balances, &definitions, &empty_input), invalid_denom(""));
+
+        let empty_output = MultiSendBuilder::new().input("account1", "denom1", 100).output("recipient", "", 100).build();
+        assert_eq!(calculate_balance_changes(&original_balances, &definitions, &empty_output), invalid_denom(""));
+
+        let transfer = MultiSendBuilder::new().input("account1", "denom1", 100).output("recipient", "denom1", 100).build();
+        let empty_definition = vec![DenomDefinition::new("", "issuer_account", Rate::ZERO, Rate::ZERO)];
+        assert_eq!(calculate_balance_changes(&original_balances, &empty_definition, &transfer), invalid_denom(""));
+
+        let padded = MultiSendBuilder::new().input("account1", "denom1 ", 100).output("recipient", "denom1 ", 100).build();
+        assert_eq!(calculate_balance_changes(&original_balances, &definitions, &padded), invalid_denom("denom1 "));
+
+        let padded_address = MultiSendBuilder::new().input("account1", "denom1", 100).output(" recipient", "denom1", 100).build();
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &padded_address),
+            Err(BalanceError::InvalidAddress { address: " recipient".to_string() })
+        );
+
+        let empty_original = vec![Balance::new("account1", vec![Coin::new("", 1000)])];
+        assert_eq!(calculate_balance_changes(&empty_original, &definitions, &transfer), invalid_denom(""));
+    }
+
+    #[test]
+    fn test_allow_undefined_denoms() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000), Coin::new("denom3", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom1", 100)
+            .input("account1", "denom3", 100)
+            .output("account_recipient", "denom1", 100)
+            .output("account_recipient", "denom3", 100)
+            .build();
+
+        assert_eq!(
+            calculate_balance_changes_with_options(&original_balances, &definitions, &multi_send_tx, &CalculationOptions::default()),
+            Err(BalanceError::MissingDefinition { denom: "denom3".to_string() })
+        );
+        let options = CalculationOptions { allow_undefined_denoms: true, ..CalculationOptions::default() };
+        assert_eq!(
+            calculate_balance_changes_with_options(&original_balances, &definitions, &multi_send_tx, &options),
+            Ok(vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 100), Coin::new("denom3", 100)]),
+                Balance::new("account1", vec![Coin::new("denom1", -115), Coin::new("denom3", -100)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 5)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_calculation_options() {
+        assert_eq!(CalculationOptions::default(), CalculationOptions { rounding: RoundingMode::Ceil, allow_undefined_denoms: false, issuer_can_overdraw: false, burn_address: None, merge_duplicate_denoms: false, allow_imbalance: false, imbalance_sink: None, address_validator: None, max_inputs: None, max_distinct_denoms: None, sort_output: false, collect_warnings: false, fee_basis: FeeBasis::Independent, collect_diagnostics: false });
+
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000), Coin::new("denom3", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom1", 33)
+            .input("account1", "denom3", 7)
+            .output("account_recipient", "denom1", 33)
+            .output("account_recipient", "denom3", 7)
+            .build();
+        let options = CalculationOptions { rounding: RoundingMode::Ceil, allow_undefined_denoms: true, issuer_can_overdraw: false, burn_address: None, merge_duplicate_denoms: false, allow_imbalance: false, imbalance_sink: None, address_validator: None, max_inputs: None, max_distinct_denoms: None, sort_output: false, collect_warnings: false, fee_basis: FeeBasis::Independent, collect_diagnostics: false };
+
+        // Burn 3.3 and commission 1.65 round up to 4 and 2; denom3 moves fee-free.
+        assert_eq!(
+            calculate_balance_changes_with_options(&original_balances, &definitions, &multi_send_tx, &options),
+            Ok(vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 33), Coin::new("denom3", 7)]),
+                Balance::new("account1", vec![Coin::new("denom1", -39), Coin::new("denom3", -7)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 2)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_rounding_modes_change_deductions() {
+        let original_balances = vec![
+            Balance::new("account1", vec![Coin::new("denom1", 1000)]),
+            Balance::new("account2", vec![Coin::new("denom1", 1000)]),
+        ];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(1400))];
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom1", 15)
+            .input("account2", "denom1", 10)
+            .output("account_recipient", "denom1", 25)
+            .build();
+
+        // A burn of 2.5 and a commission of 3.5: Ceil charges 3 and 4, Floor 2
+        // and 3, HalfUp 3 and 4, HalfEven 2 and 4.
+        for (rounding, account1, account2, commission) in [
+            (RoundingMode::Ceil, -19, -13, 4),
+            (RoundingMode::Floor, -18, -12, 3),
+            (RoundingMode::HalfUp, -19, -13, 4),
+            (RoundingMode::HalfEven, -18, -13, 4),
+        ]
+        {
+            let options = CalculationOptions { rounding, ..CalculationOptions::default() };
+            assert_eq!(
+                calculate_balance_changes_with_options(&original_balances, &definitions, &multi_send_tx, &options),
+                Ok(vec![
+                    Balance::new("account_recipient", vec![Coin::new("denom1", 25)]),
+                    Balance::new("account1", vec![Coin::new("denom1", account1)]),
+                    Balance::new("account2", vec![Coin::new("denom1", account2)]),
+                    Balance::new("issuer_account", vec![Coin::new("denom1", commission)]),
+                ]),
+                "{:?}",
+                rounding
+            );
+        }
+    }
+
+    #[test]
+    fn test_issuer_can_overdraw_mints() {
+        let original_balances = vec![Balance::new("issuer_account", vec![Coin::new("denom1", 100)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        let multi_send_tx = MultiSendBuilder::new().input("issuer_account", "denom1", 250).output("account_recipient", "denom1", 250).build();
+
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Err(BalanceError::InsufficientBalance {
+                address: "issuer_account".to_string(),
+                denom: "denom1".to_string(),
+                have: 100,
+                need: 250,
+            })
+        );
+
+        // The issuer spends the 100 it holds and mints the other 150.
+        let options = CalculationOptions { issuer_can_overdraw: true, ..CalculationOptions::default() };
+        let (balance_changes, summary) = calculate(&original_balances, &definitions, &multi_send_tx, &options).unwrap();
+        assert_eq!(
+            balance_changes,
+            vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 250)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", -100)]),
+            ]
+        );
+        assert_eq!(summary.minted, BTreeMap::from([("denom1".to_string(), 250 - 100)]));
+
+        // An issuer with no balance at all mints the whole amount, so its own
+        // balance doesn't change.
+        assert_eq!(
+            calculate_balance_changes_with_options(&[], &definitions, &multi_send_tx, &options),
+            Ok(vec![Balance::new("account_recipient", vec![Coin::new("denom1", 250)])])
+        );
+    }
+
+    #[test]
+    fn test_balances_from_tuples() {
+        let verbose = vec![
+            Balance {
+                address: "account1".to_string(),
+                coins: vec![
+                    Coin { denom: "denom1".to_string(), amount: Amount::new(1000) },
+                    Coin { denom: "denom2".to_string(), amount: Amount::new(2000) },
+                ],
+            },
+            Balance {
+                address: "account2".to_string(),
+                coins: vec![
+                    Coin { denom: "denom1".to_string(), amount: Amount::new(500) },
+                    Coin { denom: "denom2".to_string(), amount: Amount::new(1500) },
+                ],
+            },
+        ];
+        let compact = Balance::from_tuples(vec![("account1", vec![("denom1", 1000), ("denom2", 2000)]), ("account2", vec![("denom1", 500), ("denom2", 1500)])]);
+        assert_eq!(compact, verbose);
+        assert_eq!(Balance::from(("account1".to_string(), vec![("denom1", 1000), ("denom2", 2000)])), verbose[0]);
+        assert_eq!(Balance::from(("account2", Vec::<(&str, i128)>::new())), Balance::new("account2", vec![]));
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("1000"), Ok(1000));
+        assert_eq!(parse_amount("0"), Ok(0));
+        assert_eq!(parse_amount("170141183460469231731687303715884105727"), Ok(i128::MAX));
+        assert_eq!(Coin::from_str_amount("denom1", "007"), Ok(Coin::new("denom1", 7)));
+
+        for amount in ["", "-1", "+1", " 1", "1 ", "1.5", "1e3", "0x10", "170141183460469231731687303715884105728"] {
+            assert_eq!(parse_amount(amount), Err(BalanceError::InvalidAmount { amount: amount.to_string() }), "{:?}", amount);
+        }
+        assert_eq!(Coin::from_str_amount("denom1", "-5"), Err(BalanceError::InvalidAmount { amount: "-5".to_string() }));
+    }
+
+    #[test]
+    fn test_mismatch_reports_totals() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000)]), Balance::new("account2", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        // 300 + 200 sent, 400 + 50 to the issuer received.
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom1", 300)
+            .input("account2", "denom1", 200)
+            .output("account_recipient", "denom1", 400)
+            .output("issuer_account", "denom1", 50)
+            .build();
+
+        let error = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap_err();
+        assert_eq!(error, BalanceError::InputOutputMismatch { denom: "denom1".to_string(), input_total: 500, output_total: 450 });
+        assert_eq!(error.to_string(), "Input and output does not match for denom1: inputs total 500, outputs total 450, difference -50");
+    }
+
+    #[test]
+    fn test_match_check_counts_issuer_on_both_sides() {
+        let original_balances = vec![
+            Balance::new("issuer_account", vec![Coin::new("denom1", 200)]),
+            Balance::new("account1", vec![Coin::new("denom1", 1000)]),
+        ];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        // 200 + 300 in and 400 + 100 out balance only with the issuer counted on
+        // both sides. The commission of 15 on the 300 base isn't an output.
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("issuer_account", "denom1", 200)
+            .input("account1", "denom1", 300)
+            .output("account_recipient", "denom1", 400)
+            .output("issuer_account", "denom1", 100)
+            .build();
+
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Ok(vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 400)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 100 - 200 + 15)]),
+                Balance::new("account1", vec![Coin::new("denom1", -345)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_balance_changes_are_conserved() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+
+        let (balance_changes, summary) = calculate_balance_changes_with_summary(&original_balances, &definitions, &multi_send_tx).unwrap();
+        assert_conserved(&balance_changes, &summary, false);
+    }
+
+    #[test]
+    #[should_panic(expected = "balance changes for denom1 net to 5, but 0 was minted and 0 burned")]
+    fn test_unconserved_changes_panic() {
+        let balance_changes = vec![Balance::new("account1", vec![Coin::new("denom1", -100)]), Balance::new("account2", vec![Coin::new("denom1", 105)])];
+        assert_conserved(&balance_changes, &TransferSummary::default(), false);
+    }
+
+    #[test]
+    fn test_burn_address_receives_burns() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+
+        let options = CalculationOptions { burn_address: Some("burn_account".to_string()), ..CalculationOptions::default() };
+        let (balance_changes, summary) = calculate(&original_balances, &definitions, &multi_send_tx, &options).unwrap();
+        assert_eq!(summary.burned, BTreeMap::from([("denom1".to_string(), 50), ("denom2".to_string(), 300)]));
+        assert_eq!(
+            balance_changes,
+            vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 500), Coin::new("denom2", 1500)]),
+                Balance::new("account1", vec![Coin::new("denom1", -345), Coin::new("denom2", -1300)]),
+                Balance::new("account2", vec![Coin::new("denom1", -230), Coin::new("denom2", -650)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 25), Coin::new("denom2", 150)]),
+                Balance::new("burn_account", vec![Coin::new("denom1", 50), Coin::new("denom2", 300)]),
+            ]
+        );
+
+        let options = CalculationOptions { burn_address: Some("burn account".to_string()), ..CalculationOptions::default() };
+        assert_eq!(
+            calculate_balance_changes_with_options(&original_balances, &definitions, &multi_send_tx, &options),
+            Err(BalanceError::InvalidAddress { address: "burn account".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_for_each_balance_change() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+
+        let mut streamed = Vec::new();
+        for_each_balance_change(&original_balances, &definitions, &multi_send_tx, |balance| streamed.push(balance)).unwrap();
+        assert_eq!(streamed, calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap());
+
+        let mut called = false;
+        let multi_send_tx = MultiSendBuilder::new().input("account2", "denom1", 501).output("account1", "denom1", 501).build();
+        assert!(for_each_balance_change(&original_balances, &definitions, &multi_send_tx, |_| called = true).is_err());
+        assert!(!called);
+    }
+
+    #[test]
+    fn test_balance_change_for() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+
+        let balance_changes = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap();
+        for address in ["account1", "account_recipient", "issuer_account"] {
+            let expected = balance_changes.iter().find(|balance| balance.address == address).cloned();
+            assert_eq!(balance_change_for(address, &original_balances, &definitions, &multi_send_tx), Ok(expected));
+        }
+        assert_eq!(balance_change_for("account3", &original_balances, &definitions, &multi_send_tx), Ok(None));
+
+        // account1 is fine, but the transaction as a whole is not.
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 10).input("account2", "denom1", 501).output("account3", "denom1", 511).build();
+        assert!(matches!(balance_change_for("account1", &original_balances, &definitions, &multi_send_tx), Err(BalanceError::InsufficientBalance { .. })));
+
+        // The recipient's balance overflows, not account1's.
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 1000)]), ("account_recipient", vec![("denom1", i128::MAX)])]);
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 10).output("account_recipient", "denom1", 10).build();
+        let overflowed = Err(BalanceError::Overflow { denom: "denom1".to_string() });
+        assert_eq!(calculate_balance_changes(&original_balances, &definitions, &multi_send_tx), overflowed);
+        assert_eq!(balance_change_for("account1", &original_balances, &definitions, &multi_send_tx), overflowed.map(|_: Vec<Balance>| None));
+    }
+
+    #[test]
+    fn test_commission_listed_as_output_rejected() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        // The 25 commission on 500 is credited by the calculation; listing it
+        // (or more) as an output to the issuer is unbacked by any input.
+        for commission_output in [25, 1_000_000] {
+            let multi_send_tx = MultiSendBuilder::new()
+                .input("account1", "denom1", 500)
+                .output("account_recipient", "denom1", 500)
+                .output("issuer_account", "denom1", commission_output)
+                .build();
+            assert_eq!(
+                calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+                Err(BalanceError::CommissionMismatch { denom: "denom1".to_string(), claimed: commission_output, collected: 25 })
+            );
+        }
+        let error = BalanceError::CommissionMismatch { denom: "denom1".to_string(), claimed: 25, collected: 25 };
+        assert_eq!(error.to_string(), "Outputs to the issuer of denom1 claim 25 not covered by inputs; commission of 25 is credited by the calculation");
+
+        // An excess the issuer's outputs don't account for is a plain mismatch.
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 500).output("account_recipient", "denom1", 520).output("issuer_account", "denom1", 10).build();
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Err(BalanceError::InputOutputMismatch { denom: "denom1".to_string(), input_total: 500, output_total: 530 })
+        );
+    }
+
+    #[test]
+    fn test_merge_duplicate_denoms() {
+        let mut balance = Balance::new("account1", vec![Coin::new("denom1", 100), Coin::new("denom2", 20), Coin::new("denom1", 50)]);
+        balance.normalize().unwrap();
+        assert_eq!(balance, Balance::new("account1", vec![Coin::new("denom1", 150), Coin::new("denom2", 20)]));
+
+        let mut overflowing = Balance::new("account1", vec![Coin::new("denom1", i128::MAX), Coin::new("denom1", 1)]);
+        assert_eq!(overflowing.normalize(), Err(BalanceError::Overflow { denom: "denom1".to_string() }));
+        assert_eq!(overflowing.coins.len(), 2);
+
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 600), Coin::new("denom1", 400)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 300).input("account1", "denom1", 200).output("account_recipient", "denom1", 500).build();
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Err(BalanceError::DuplicateDenom { address: "account1".to_string(), denom: "denom1".to_string() })
+        );
+
+        let options = CalculationOptions { merge_duplicate_denoms: true, ..CalculationOptions::default() };
+        assert_eq!(
+            calculate_balance_changes_with_options(&original_balances, &definitions, &multi_send_tx, &options),
+            Ok(vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 500)]),
+                Balance::new("account1", vec![Coin::new("denom1", -575)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 25)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_supply_delta() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+
+        let (_, summary) = calculate_balance_changes_with_summary(&original_balances, &definitions, &multi_send_tx).unwrap();
+        let negated_burn: BTreeMap<String, i128> = summary.burned.into_iter().map(|(denom, burned)| (denom, -burned)).collect();
+        assert_eq!(supply_delta(&original_balances, &definitions, &multi_send_tx), Ok(negated_burn));
+    }
+
+    #[test]
+    fn test_fees_on_single_unit_exceed_amount() {
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(9000), Rate::from_bps(1000))];
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 1).output("account_recipient", "denom1", 1).build();
+
+        // 0.9 and 0.1 both round up to 1, so sending 1 costs 3.
+        assert_eq!(
+            calculate_deductions(&[Balance::new("account1", vec![Coin::new("denom1", 3)])], &definitions, &multi_send_tx),
+            Ok(vec![DeductionDetail { address: "account1".to_string(), denom: "denom1".to_string(), base: 1, burn: 1, commission: 1 }])
+        );
+        for have in [1, 2] {
+            assert_eq!(
+                calculate_balance_changes(&[Balance::new("account1", vec![Coin::new("denom1", have)])], &definitions, &multi_send_tx),
+                Err(BalanceError::InsufficientBalance { address: "account1".to_string(), denom: "denom1".to_string(), have, need: 3 })
+            );
+        }
+
+        // Rounding down instead lets the unit through fee-free.
+        let options = CalculationOptions { rounding: RoundingMode::Floor, ..CalculationOptions::default() };
+        assert_eq!(
+            calculate_balance_changes_with_options(&[Balance::new("account1", vec![Coin::new("denom1", 1)])], &definitions, &multi_send_tx, &options),
+            Ok(vec![Balance::new("account_recipient", vec![Coin::new("denom1", 1)]), Balance::new("account1", vec![Coin::new("denom1", -1)])])
+        );
+    }
+
+    #[test]
+    fn test_display_balances() {
+        let original_balances = [
+            Balance::new("account1", vec![Coin::new("denom1", 1000), Coin::new("denom2", 2000)]),
+            Balance::new("account2", vec![Coin::new("denom1", -345)]),
+            Balance::new("account3", vec![]),
+        ];
+        assert_eq!(original_balances[0].coins[0].to_string(), "1000denom1");
+        let formatted: Vec<String> = original_balances.iter().map(Balance::to_string).collect();
+        assert_eq!(formatted, ["account1: 1000denom1, 2000denom2", "account2: -345denom1", "account3:"]);
+    }
+
+    #[test]
+    fn test_fee_caps() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000)]), Balance::new("account2", vec![Coin::new("denom1", 500)])];
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 300).input("account2", "denom1", 200).output("account_recipient", "denom1", 500).build();
+        let deductions = |definition: DenomDefinition| {
+            let deductions = calculate_deductions(&original_balances, &[definition], &multi_send_tx).unwrap();
+            deductions.iter().map(|deduction| (deduction.burn, deduction.commission)).collect::<Vec<_>>()
+        };
+        let definition = DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500));
+
+        // Uncapped: a burn of 50 and a commission of 25 on the 500 sent.
+        assert_eq!(deductions(definition.clone()), [(30, 15), (20, 10)]);
+        assert_eq!(deductions(definition.clone().with_max_burn(100).with_max_commission(25)), [(30, 15), (20, 10)]);
+        // A burn capped at 41 splits 24.6/16.4, the leftover unit going to the
+        // larger remainder; a zero commission cap waives it.
+        assert_eq!(deductions(definition.clone().with_max_burn(41).with_max_commission(0)), [(25, 0), (16, 0)]);
+
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &[definition.with_max_commission(-1)], &multi_send_tx),
+            Err(BalanceError::InvalidFeeCap { denom: "denom1".to_string(), cap: -1 })
+        );
+    }
+
+    #[test]
+    fn test_inputs_are_borrowed() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 500).output("account_recipient", "denom1", 500).build();
+
+        let first = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+        let second = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx);
+        assert_eq!(first, second);
+        assert_eq!(first.unwrap()[1], Balance::new("account1", vec![Coin::new("denom1", -575)]));
+        assert_eq!(multi_send_tx.inputs()[0].coins(), [Coin::new("denom1", 500)]);
+    }
+
+    #[test]
+    fn test_estimate_fees() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+
+        let mut actual: BTreeMap<String, (i128, i128)> = BTreeMap::new();
+        for deduction in calculate_deductions(&original_balances, &definitions, &multi_send_tx).unwrap() {
+            let totals = actual.entry(deduction.denom).or_default();
+            *totals = (totals.0 + deduction.burn, totals.1 + deduction.commission);
+        }
+        assert_eq!(actual, BTreeMap::from([("denom1".to_string(), (50, 25)), ("denom2".to_string(), (300, 150))]));
+        assert_eq!(estimate_fees(&definitions, &multi_send_tx), Ok(actual));
+
+        assert_eq!(estimate_fees(&definitions[1..], &multi_send_tx), Err(BalanceError::MissingDefinition { denom: "denom1".to_string() }));
+    }
+
+    #[test]
+    fn test_duplicate_input_address_rejected() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        // Each entry alone fits the balance of 1000; together they don't.
+        let multi_send_tx = MultiSend::new(
+            vec![Balance::new("account1", vec![Coin::new("denom1", 800)]), Balance::new("account1", vec![Coin::new("denom1", 800)])],
+            vec![Balance::new("account_recipient", vec![Coin::new("denom1", 1600)])],
+        );
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Err(BalanceError::DuplicateAddress { address: "account1".to_string() })
+        );
+
+        // The same address may still appear once on each side.
+        let multi_send_tx = MultiSend::new(
+            vec![Balance::new("account1", vec![Coin::new("denom1", 100)])],
+            vec![Balance::new("account_recipient", vec![Coin::new("denom1", 50)]), Balance::new("account1", vec![Coin::new("denom1", 50)])],
+        );
+        assert!(calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).is_ok());
+        let multi_send_tx = MultiSend::new(
+            vec![Balance::new("account1", vec![Coin::new("denom1", 100)])],
+            vec![Balance::new("account_recipient", vec![Coin::new("denom1", 50)]), Balance::new("account_recipient", vec![Coin::new("denom1", 50)])],
+        );
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Err(BalanceError::DuplicateAddress { address: "account_recipient".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_calculate_and_verify() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+        let expected = Balance::from_tuples(vec![
+            ("issuer_account", vec![("denom2", 150), ("denom1", 25)]),
+            ("account_recipient", vec![("denom1", 500), ("denom2", 1500)]),
+            ("account1", vec![("denom1", -345), ("denom2", -1300)]),
+            ("account2", vec![("denom1", -230), ("denom2", -650), ("denom3", 0)]),
+        ]);
+        assert_eq!(calculate_and_verify(&original_balances, &definitions, &multi_send_tx, &expected), Ok(()));
+
+        let mut wrong = expected.clone();
+        wrong[2] = Balance::from(("account1", vec![("denom1", -345), ("denom2", -1000)]));
+        assert_eq!(
+            calculate_and_verify(&original_balances, &definitions, &multi_send_tx, &wrong),
+            Err(BalanceError::Mismatch { address: "account1".to_string(), denom: "denom2".to_string(), got: -1300, expected: -1000 })
+        );
+        assert_eq!(
+            calculate_and_verify(&original_balances, &definitions, &multi_send_tx, &wrong).unwrap_err().to_string(),
+            "account1 changes by -1300 denom2 but -1000 was expected"
+        );
+        // An address left out of `expected` counts as unchanged.
+        assert_eq!(
+            calculate_and_verify(&original_balances, &definitions, &multi_send_tx, &expected[1..]),
+            Err(BalanceError::Mismatch { address: "issuer_account".to_string(), denom: "denom1".to_string(), got: 25, expected: 0 })
+        );
+    }
+
+    #[test]
+    fn test_format_with_precision() {
+        let definition = DenomDefinition::new("denom1", "issuer_account", Rate::ZERO, Rate::ZERO).with_precision(6);
+        assert_eq!(definition.precision(), Some(6));
+        assert_eq!(Coin::new("denom1", 1234500).format_with_precision(6), "1.2345");
+        assert_eq!(Coin::new("denom1", 2_000_000).format_with_precision(6), "2");
+        assert_eq!(Coin::new("denom1", 5).format_with_precision(6), "0.000005");
+        assert_eq!(Coin::new("denom1", -1234500).format_with_precision(6), "-1.2345");
+        assert_eq!(Coin::new("denom1", 0).format_with_precision(6), "0");
+        assert_eq!(Coin::new("denom1", 1234500).format_with_precision(0), "1234500");
+        assert_eq!(Coin::new("denom1", i128::MIN).format_with_precision(38), "-1.70141183460469231731687303715884105728");
+    }
+
+    #[test]
+    fn test_transaction_report() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+
+        // denom1: 10% burn and 5% commission of 500; denom2: 20% and 10% of 1500.
+        assert_eq!(
+            transaction_report(&original_balances, &definitions, &multi_send_tx),
+            Ok(TransactionReport {
+                denoms: BTreeMap::from([
+                    ("denom1".to_string(), DenomReport { transferred: 500, burned: 50, commission: 25, net_supply: -50 }),
+                    ("denom2".to_string(), DenomReport { transferred: 1500, burned: 300, commission: 150, net_supply: -300 }),
+                ]),
+            })
+        );
+    }
+
+    #[test]
+    fn test_issuer_pays_fees_only_on_denoms_it_does_not_issue() {
+        let original_balances = vec![Balance::new("issuer_account", vec![Coin::new("denom1", 300), Coin::new("denom2", 1300)])];
+        let definitions = vec![
+            DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500)),
+            DenomDefinition::new("denom2", "other_issuer", Rate::from_bps(2000), Rate::from_bps(1000)),
+        ];
+        // One input balance holding a fee-free coin and a fee-bearing one.
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("issuer_account", "denom1", 300)
+            .input("issuer_account", "denom2", 1000)
+            .output("account_recipient", "denom1", 300)
+            .output("account_recipient", "denom2", 1000)
+            .build();
+
+        assert_eq!(
+            calculate_deductions(&original_balances, &definitions, &multi_send_tx),
+            Ok(vec![
+                DeductionDetail { address: "issuer_account".to_string(), denom: "denom1".to_string(), base: 300, burn: 0, commission: 0 },
+                DeductionDetail { address: "issuer_account".to_string(), denom: "denom2".to_string(), base: 1000, burn: 200, commission: 100 },
+            ])
+        );
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Ok(vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 300), Coin::new("denom2", 1000)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", -300), Coin::new("denom2", -1300)]),
+                Balance::new("other_issuer", vec![Coin::new("denom2", 100)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_diff_balances() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+
+        let final_balances = apply_multi_send(&original_balances, &definitions, &multi_send_tx).unwrap();
+        let diff = diff_balances(&original_balances, &final_balances).unwrap();
+        assert_eq!(diff, calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap());
+        assert_eq!(diff[0], Balance::new("account_recipient", vec![Coin::new("denom1", 500), Coin::new("denom2", 1500)]));
+
+        // Unchanged coins are dropped, and coins missing from `after` count as zero.
+        let before = Balance::from_tuples(vec![("account1", vec![("denom1", 100), ("denom2", 5)]), ("account2", vec![("denom1", 7)])]);
+        let after = Balance::from_tuples(vec![("account1", vec![("denom1", 100), ("denom2", 8)])]);
+        assert_eq!(diff_balances(&before, &after), Ok(Balance::from_tuples(vec![("account1", vec![("denom2", 3)]), ("account2", vec![("denom1", -7)])])));
+
+        // Malformed balances and deltas out of range are errors.
+        let duplicated = Balance::from_tuples(vec![("account1", vec![("denom1", 5), ("denom1", 7)])]);
+        assert_eq!(diff_balances(&duplicated, &after), Err(BalanceError::DuplicateDenom { address: "account1".to_string(), denom: "denom1".to_string() }));
+        assert_eq!(diff_balances(&before, &Balance::from_tuples(vec![("a b", vec![("denom1", 1)])])), Err(BalanceError::InvalidAddress { address: "a b".to_string() }));
+        let low = Balance::from_tuples(vec![("account1", vec![("denom1", -2)])]);
+        let high = Balance::from_tuples(vec![("account1", vec![("denom1", i128::MAX)])]);
+        assert_eq!(diff_balances(&low, &high), Err(BalanceError::Overflow { denom: "denom1".to_string() }));
+    }
+
+    #[test]
+    fn test_imbalance_goes_to_sink() {
+        let original_balances = vec![Balance::new("account1", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "issuer_account", Rate::from_bps(1000), Rate::from_bps(500))];
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 300).output("account_recipient", "denom1", 200).build();
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Err(BalanceError::InputOutputMismatch { denom: "denom1".to_string(), input_total: 300, output_total: 200 })
+        );
+
+        // Fees are charged on the 200 actually transferred, not the 100 remainder.
+        let options = CalculationOptions { allow_imbalance: true, imbalance_sink: Some("sink_account".to_string()), ..CalculationOptions::default() };
+        let (balance_changes, summary) = calculate(&original_balances, &definitions, &multi_send_tx, &options).unwrap();
+        assert_eq!(
+            balance_changes,
+            vec![
+                Balance::new("account_recipient", vec![Coin::new("denom1", 200)]),
+                Balance::new("account1", vec![Coin::new("denom1", -330)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 10)]),
+                Balance::new("sink_account", vec![Coin::new("denom1", 100)]),
+            ]
+        );
+        assert_eq!(summary.burned, BTreeMap::from([("denom1".to_string(), 20)]));
+
+        // Without a sink the remainder is burned along with the fee.
+        let options = CalculationOptions { allow_imbalance: true, ..CalculationOptions::default() };
+        let (balance_changes, summary) = calculate(&original_balances, &definitions, &multi_send_tx, &options).unwrap();
+        assert_eq!(balance_changes.len(), 3);
+        assert_eq!(summary.burned, BTreeMap::from([("denom1".to_string(), 120)]));
+
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 200).output("account_recipient", "denom1", 300).build();
+        assert_eq!(
+            calculate_balance_changes_with_options(&original_balances, &definitions, &multi_send_tx, &options),
+            Err(BalanceError::InputOutputMismatch { denom: "denom1".to_string(), input_total: 200, output_total: 300 })
+        );
+    }
+
+    #[test]
+    fn test_address_validator() {
+        let original_balances = vec![Balance::new("core1sender", vec![Coin::new("denom1", 1000)])];
+        let definitions = vec![DenomDefinition::new("denom1", "core1issuer", Rate::from_bps(1000), Rate::from_bps(500))];
+        let multi_send_tx = MultiSendBuilder::new().input("core1sender", "denom1", 100).output("cosmos1recipient", "denom1", 100).build();
+        assert!(calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).is_ok());
+
+        let options = CalculationOptions { address_validator: Some(|address| address.starts_with("core")), ..CalculationOptions::default() };
+        assert_eq!(
+            calculate_balance_changes_with_options(&original_balances, &definitions, &multi_send_tx, &options),
+            Err(BalanceError::InvalidAddress { address: "cosmos1recipient".to_string() })
+        );
+
+        let definitions = vec![DenomDefinition::new("denom1", "issuer", Rate::from_bps(1000), Rate::from_bps(500))];
+        let multi_send_tx = MultiSendBuilder::new().input("core1sender", "denom1", 100).output("core1recipient", "denom1", 100).build();
+        assert_eq!(
+            calculate_balance_changes_with_options(&original_balances, &definitions, &multi_send_tx, &options),
+            Err(BalanceError::InvalidAddress { address: "issuer".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_denom_definition_with_bps() {
+        let definition = DenomDefinition::with_bps("denom1", "issuer_account", 1000, 500);
+        assert_eq!(definition, DenomDefinition::new("denom1", "issuer_account", "0.1".parse().unwrap(), "0.05".parse().unwrap()));
+        assert_eq!(definition.commission_rate().to_string(), "0.05");
+
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 1000)]), ("account2", vec![("denom1", 500)])]);
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 300).input("account2", "denom1", 200).output("account_recipient", "denom1", 500).build();
+        let deductions = calculate_deductions(&original_balances, &[definition], &multi_send_tx).unwrap();
+        assert_eq!(
+            deductions.iter().map(|deduction| (deduction.burn, deduction.commission)).collect::<Vec<_>>(),
+            vec![(30, 15), (20, 10)]
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let (_, _, multi_send_tx) = sample();
+        assert_eq!(stats(&multi_send_tx), TransactionStats { affected_accounts: 3, distinct_denoms: 2, total_inputs: 2, total_outputs: 1 });
+
+        // An address on both sides is one account.
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 10).output("account1", "denom1", 4).output("account2", "denom1", 6).build();
+        assert_eq!(stats(&multi_send_tx), TransactionStats { affected_accounts: 2, distinct_denoms: 1, total_inputs: 1, total_outputs: 2 });
+        assert_eq!(stats(&MultiSend::new(Vec::new(), Vec::new())), TransactionStats::default());
+    }
+
+    #[test]
+    fn test_zero_changes_are_omitted() {
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 1000), ("denom2", 2000)]), ("account2", vec![("denom1", 500)])]);
+        let definitions = vec![
+            DenomDefinition::with_bps("denom1", "issuer_account", 0, 0),
+            DenomDefinition::with_bps("denom2", "issuer_account", 2000, 1000),
+        ];
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom1", 300)
+            .input("account1", "denom2", 0)
+            .input("account2", "denom1", 0)
+            .output("account_recipient", "denom1", 300)
+            .output("account_recipient", "denom2", 0)
+            .build();
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Ok(vec![Balance::new("account_recipient", vec![Coin::new("denom1", 300)]), Balance::new("account1", vec![Coin::new("denom1", -300)])])
+        );
+
+        // Sending to yourself nets to nothing at all.
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 300).output("account1", "denom1", 300).build();
+        assert_eq!(calculate_balance_changes(&original_balances, &definitions, &multi_send_tx), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_limits() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+
+        let options = CalculationOptions { max_inputs: Some(2), max_distinct_denoms: Some(2), ..CalculationOptions::default() };
+        assert!(calculate_balance_changes_with_options(&original_balances, &definitions, &multi_send_tx, &options).is_ok());
+
+        let options = CalculationOptions { max_inputs: Some(1), ..CalculationOptions::default() };
+        assert_eq!(
+            calculate_balance_changes_with_options(&original_balances, &definitions, &multi_send_tx, &options),
+            Err(BalanceError::LimitExceeded { limit: "inputs".to_string(), max: 1 })
+        );
+
+        // Limits are checked before anything else, even when the transaction is invalid anyway.
+        let options = CalculationOptions { max_distinct_denoms: Some(1), ..CalculationOptions::default() };
+        assert_eq!(
+            calculate_balance_changes_with_options(&[], &[], &multi_send_tx, &options),
+            Err(BalanceError::LimitExceeded { limit: "distinct denoms".to_string(), max: 1 })
+        );
+        assert_eq!(BalanceError::LimitExceeded { limit: "distinct denoms".to_string(), max: 1 }.to_string(), "Transaction has more than 1 distinct denoms");
+    }
+
+    #[test]
+    fn test_fee_total_is_rounded_once() {
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 100)]), ("account2", vec![("denom1", 100)]), ("account3", vec![("denom1", 100)])]);
+        let definitions = vec![DenomDefinition::with_bps("denom1", "issuer_account", 1000, 0)];
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom1", 5)
+            .input("account2", "denom1", 5)
+            .input("account3", "denom1", 5)
+            .output("account_recipient", "denom1", 15)
+            .build();
+
+        // Each sender owes 0.5, so ceiling every share would burn 3. Rounding the
+        // total of 1.5 half up burns 2, and the shares add up to exactly that.
+        let options = CalculationOptions { rounding: RoundingMode::HalfUp, ..CalculationOptions::default() };
+        let (balance_changes, summary) = calculate(&original_balances, &definitions, &multi_send_tx, &options).unwrap();
+        assert_eq!(summary.burned, BTreeMap::from([("denom1".to_string(), 2)]));
+        let debited: i128 = balance_changes.iter().filter(|balance| balance.address != "account_recipient").flat_map(Balance::coins).map(|coin| coin.amount().get()).sum();
+        assert_eq!(debited, -17);
+    }
+
+    #[test]
+    fn test_is_valid() {
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 1000)])]);
+        let definitions = vec![DenomDefinition::with_bps("denom1", "issuer_account", 1000, 500)];
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 300).output("account_recipient", "denom1", 300).build();
+        assert!(is_valid(&original_balances, &definitions, &multi_send_tx));
+
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 1000).output("account_recipient", "denom1", 1000).build();
+        assert!(!is_valid(&original_balances, &definitions, &multi_send_tx));
+    }
+
+    #[test]
+    fn test_sort_output() {
+        let (original_balances, definitions, _) = sample();
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account2", "denom2", 500)
+            .input("account2", "denom1", 200)
+            .input("account1", "denom1", 300)
+            .input("account1", "denom2", 1000)
+            .output("account_recipient", "denom2", 1500)
+            .output("account_recipient", "denom1", 500)
+            .build();
+
+        let options = CalculationOptions { sort_output: true, ..CalculationOptions::default() };
+        assert_eq!(
+            calculate_balance_changes_with_options(&original_balances, &definitions, &multi_send_tx, &options),
+            Ok(vec![
+                Balance::new("account1", vec![Coin::new("denom1", -345), Coin::new("denom2", -1300)]),
+                Balance::new("account2", vec![Coin::new("denom1", -230), Coin::new("denom2", -650)]),
+                Balance::new("account_recipient", vec![Coin::new("denom1", 500), Coin::new("denom2", 1500)]),
+                Balance::new("issuer_account", vec![Coin::new("denom1", 25), Coin::new("denom2", 150)]),
+            ])
+        );
+        assert_eq!(calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap()[0].address(), "account_recipient");
+    }
+
+    #[test]
+    fn test_summary_flags_issuers() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+
+        let (_, summary) = calculate_balance_changes_with_summary(&original_balances, &definitions, &multi_send_tx).unwrap();
+        assert_eq!(summary.issuers, BTreeSet::from([("issuer_account".to_string(), "denom1".to_string()), ("issuer_account".to_string(), "denom2".to_string())]));
+
+        // An issuer of one denom isn't flagged for another it merely holds.
+        let definitions = vec![DenomDefinition::with_bps("denom1", "account1", 1000, 500), DenomDefinition::with_bps("denom2", "issuer_account", 2000, 1000)];
+        let (_, summary) = calculate_balance_changes_with_summary(&original_balances, &definitions, &multi_send_tx).unwrap();
+        assert_eq!(summary.issuers, BTreeSet::from([("account1".to_string(), "denom1".to_string()), ("issuer_account".to_string(), "denom2".to_string())]));
+    }
+
+    #[test]
+    fn test_result_order_does_not_depend_on_maps() {
+        // Run with and without `std`: hash maps and B-trees iterate denoms in
+        // different orders, but the result follows the transaction's order.
+        let denoms: Vec<String> = (0..20).rev().map(|d| format!("denom{}", d)).collect();
+        let original_balances = vec![Balance::new("account1", denoms.iter().map(|denom| Coin::new(denom, 1000)).collect())];
+        let definitions: Vec<DenomDefinition> = denoms.iter().map(|denom| DenomDefinition::with_bps(denom, "issuer_account", 1000, 500)).collect();
+        let mut builder = MultiSendBuilder::new();
+        for denom in &denoms {
+            builder = builder.input("account1", denom, 100).output("account_recipient", denom, 100);
+        }
+
+        let (balance_changes, summary) = calculate_balance_changes_with_summary(&original_balances, &definitions, &builder.build()).unwrap();
+        let coins = |amount: i128| denoms.iter().map(|denom| Coin::new(denom, amount)).collect::<Vec<_>>();
+        assert_eq!(
+            balance_changes,
+            vec![Balance::new("account_recipient", coins(100)), Balance::new("account1", coins(-115)), Balance::new("issuer_account", coins(5))]
+        );
+        assert_eq!(summary.burned, denoms.iter().map(|denom| (denom.clone(), 10)).collect());
+    }
+
+    #[test]
+    fn test_mismatches_are_reported_in_denom_order() {
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom_b", 1000), ("denom_c", 1000), ("denom_d", 1000)])]);
+        let definitions: Vec<DenomDefinition> = ["denom_a", "denom_b", "denom_c", "denom_d"].iter().map(|denom| DenomDefinition::with_bps(*denom, "issuer_account", 0, 0)).collect();
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom_d", 10)
+            .input("account1", "denom_b", 10)
+            .input("account1", "denom_c", 10)
+            .output("account_recipient", "denom_c", 10)
+            .output("account_recipient", "denom_d", 9)
+            .output("account_recipient", "denom_b", 11)
+            .output("account_recipient", "denom_a", 5)
+            .build();
+
+        let mismatch = |denom: &str, input_total, output_total| BalanceError::InputOutputMismatch { denom: denom.to_string(), input_total, output_total };
+        assert_eq!(
+            calculate_balance_changes_collecting(&original_balances, &definitions, &multi_send_tx),
+            Err(vec![mismatch("denom_a", 0, 5), mismatch("denom_b", 10, 11), mismatch("denom_d", 10, 9)])
+        );
+        assert_eq!(calculate_balance_changes(&original_balances, &definitions, &multi_send_tx), Err(mismatch("denom_a", 0, 5)));
+    }
+
+    #[test]
+    fn test_split_by_denom() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+
+        let parts = split_by_denom(&multi_send_tx);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(
+            parts["denom1"],
+            MultiSendBuilder::new().input("account1", "denom1", 300).input("account2", "denom1", 200).output("account_recipient", "denom1", 500).build()
+        );
+
+        let mut combined = Vec::new();
+        for part in parts.values() {
+            combined.extend(calculate_balance_changes(&original_balances, &definitions, part).unwrap());
+        }
+        assert_eq!(calculate_and_verify(&original_balances, &definitions, &multi_send_tx, &combined), Ok(()));
+
+        // Duplicates are split as they are, for each part to reject.
+        let duplicated = Balance::new("account1", vec![Coin::new("denom1", 1), Coin::new("denom2", 2), Coin::new("denom1", 3)]);
+        let parts = split_by_denom(&MultiSend::new(vec![duplicated.clone(), duplicated], Vec::new()));
+        let entry = Balance::from_tuples(vec![("account1", vec![("denom1", 1), ("denom1", 3)])]).remove(0);
+        assert_eq!(parts["denom1"], MultiSend::new(vec![entry.clone(), entry], Vec::new()));
+    }
+
+    #[test]
+    fn test_issuer_without_balance_receives_commission() {
+        // Commission is credited the same way as outputs, so an issuer that held
+        // nothing before gets a new entry rather than an UnknownAddress error.
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 1000)])]);
+        let definitions = vec![DenomDefinition::with_bps("denom1", "issuer_account", 1000, 500)];
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 400).output("account_recipient", "denom1", 400).build();
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap(),
+            Balance::from_tuples(vec![("account_recipient", vec![("denom1", 400)]), ("account1", vec![("denom1", -460)]), ("issuer_account", vec![("denom1", 20)])])
+        );
+        assert_eq!(
+            apply_multi_send(&original_balances, &definitions, &multi_send_tx).unwrap(),
+            Balance::from_tuples(vec![("account_recipient", vec![("denom1", 400)]), ("account1", vec![("denom1", 540)]), ("issuer_account", vec![("denom1", 20)])])
+        );
+    }
+
+    #[test]
+    fn test_gross_inputs() {
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 1000)])]);
+        let definitions = vec![DenomDefinition::with_bps("denom1", "issuer_account", 1000, 500)];
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 500).output("account_recipient", "denom1", 500).build();
+        // 10% burn and 5% commission on the 500 received.
+        let gross = gross_inputs(&original_balances, &definitions, &multi_send_tx).unwrap();
+        assert_eq!(gross, Balance::from_tuples(vec![("account1", vec![("denom1", 575)])]));
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap(),
+            Balance::from_tuples(vec![("account_recipient", vec![("denom1", 500)]), ("account1", vec![("denom1", -575)]), ("issuer_account", vec![("denom1", 25)])])
+        );
+
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 574)])]);
+        assert_eq!(
+            gross_inputs(&original_balances, &definitions, &multi_send_tx),
+            Err(BalanceError::InsufficientBalance { address: "account1".to_string(), denom: "denom1".to_string(), have: 574, need: 575 })
+        );
+    }
+
+    #[test]
+    fn test_max_sendable() {
+        assert_eq!(max_sendable(1000, Rate::ZERO, Rate::ZERO), 1000);
+        assert_eq!(max_sendable(i128::MAX, Rate::ZERO, Rate::ZERO), i128::MAX);
+        // 869 owes 86.9 and 43.45, rounded up to 87 and 44.
+        assert_eq!(max_sendable(1000, Rate::from_bps(1000), Rate::from_bps(500)), 869);
+        assert_eq!(max_sendable(1000, Rate::ONE, Rate::ONE), 333);
+        assert_eq!(max_sendable(2, Rate::from_bps(1), Rate::ZERO), 1);
+        assert_eq!(max_sendable(1, Rate::from_bps(1), Rate::ZERO), 0);
+        assert_eq!(max_sendable(0, Rate::from_bps(1000), Rate::from_bps(500)), 0);
+        assert_eq!(max_sendable(-5, Rate::ZERO, Rate::ZERO), 0);
+
+        // Sending the amount succeeds and one unit more overdraws.
+        for (balance, burn_bps, commission_bps) in [(1000, 1000, 500), (1, 0, 0), (12_345, 1, 9_999), (10i128.pow(30), 1_337, 777), (7, 10_000, 0)] {
+            let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", balance)])]);
+            let definitions = vec![DenomDefinition::with_bps("denom1", "issuer_account", burn_bps, commission_bps)];
+            let send = |amount| calculate_balance_changes(&original_balances, &definitions, &MultiSendBuilder::new().input("account1", "denom1", amount).output("account_recipient", "denom1", amount).build());
+            let base = max_sendable(balance, Rate::from_bps(burn_bps), Rate::from_bps(commission_bps));
+            assert!(send(base).is_ok(), "{} at {}/{}", balance, burn_bps, commission_bps);
+            assert!(matches!(send(base + 1), Err(BalanceError::InsufficientBalance { .. })), "{} at {}/{}", balance, burn_bps, commission_bps);
+        }
+    }
+
+    #[test]
+    fn test_dust_fee_warnings() {
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 1000), ("denom2", 1000)])]);
+        let definitions = vec![DenomDefinition::with_bps("denom1", "issuer_account", 1000, 500), DenomDefinition::with_bps("denom2", "issuer_account", 1000, 0)];
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom2", 5)
+            .input("account1", "denom1", 100)
+            .output("account_recipient", "denom2", 5)
+            .output("account_recipient", "denom1", 100)
+            .build();
+        let options = CalculationOptions { collect_warnings: true, ..CalculationOptions::default() };
+        // 10% of 5 is 0.5, rounded up to 1; denom1's fees are whole units.
+        let (balance_changes, summary) = calculate_balance_changes_with_options_and_summary(&original_balances, &definitions, &multi_send_tx, &options).unwrap();
+        assert_eq!(balance_changes, calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap());
+        assert_eq!(summary.warnings, vec![Warning::DustFeeRounded { denom: "denom2".to_string(), fee: FeeKind::Burn, charged: 1 }]);
+        assert_eq!(summary.warnings[0].to_string(), "The burn on denom2 is less than one unit and was rounded to 1");
+
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 9).output("account_recipient", "denom1", 9).build();
+        let options = CalculationOptions { rounding: RoundingMode::Floor, ..options };
+        let (_, summary) = calculate_balance_changes_with_options_and_summary(&original_balances, &definitions, &multi_send_tx, &options).unwrap();
+        assert_eq!(
+            summary.warnings,
+            vec![
+                Warning::DustFeeRounded { denom: "denom1".to_string(), fee: FeeKind::Burn, charged: 0 },
+                Warning::DustFeeRounded { denom: "denom1".to_string(), fee: FeeKind::Commission, charged: 0 },
+            ]
+        );
+
+        // Nothing is collected unless asked for.
+        let (_, summary) = calculate_balance_changes_with_summary(&original_balances, &definitions, &multi_send_tx).unwrap();
+        assert!(summary.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_issuer_outside_transaction() {
+        // The issuer only holds another denom and neither sends nor receives,
+        // so its commission gets a new entry after the inputs.
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 1000)]), ("issuer_account", vec![("denom2", 50)])]);
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 400).output("account_recipient", "denom1", 400).build();
+        let definitions = vec![DenomDefinition::with_bps("denom1", "issuer_account", 1000, 500)];
+        assert_eq!(
+            apply_multi_send(&original_balances, &definitions, &multi_send_tx).unwrap(),
+            Balance::from_tuples(vec![("account_recipient", vec![("denom1", 400)]), ("account1", vec![("denom1", 540)]), ("issuer_account", vec![("denom1", 20)])])
+        );
+
+        let definitions = vec![DenomDefinition::with_bps("denom1", "", 1000, 500)];
+        let error = calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap_err();
+        assert_eq!(error, BalanceError::InvalidDefinition { denom: "denom1".to_string() });
+        assert_eq!(error.to_string(), "Denomination denom1 has no issuer");
+        let definitions = vec![DenomDefinition::with_bps("denom1", "issuer account", 1000, 500)];
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx),
+            Err(BalanceError::InvalidAddress { address: "issuer account".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_fee_basis() {
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 10_000)])]);
+        let definitions = vec![DenomDefinition::with_bps("denom1", "issuer_account", 2000, 1000)];
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 1000).output("account_recipient", "denom1", 1000).build();
+        let changes = |fee_basis| {
+            let options = CalculationOptions { fee_basis, ..CalculationOptions::default() };
+            calculate_balance_changes_with_options_and_summary(&original_balances, &definitions, &multi_send_tx, &options).unwrap()
+        };
+        // 10% commission on 1000, or on 1000 plus the 200 burned.
+        let (balance_changes, summary) = changes(FeeBasis::Independent);
+        assert_eq!(balance_changes, calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap());
+        assert_eq!((summary.burned["denom1"], summary.commission["denom1"]), (200, 100));
+        let (balance_changes, summary) = changes(FeeBasis::Sequential);
+        assert_eq!((summary.burned["denom1"], summary.commission["denom1"]), (200, 120));
+        assert_eq!(
+            balance_changes,
+            Balance::from_tuples(vec![("account_recipient", vec![("denom1", 1000)]), ("account1", vec![("denom1", -1320)]), ("issuer_account", vec![("denom1", 120)])])
+        );
+    }
+
+    #[test]
+    fn test_check_conservation() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+        let report = check_conservation(&original_balances, &definitions, &multi_send_tx).unwrap();
+        assert!(report.balanced);
+        assert_eq!(report.denoms["denom1"], DenomConservation { debited: 575, credited: 525, burned: 50, commission: 25 });
+        assert_eq!(report.denoms["denom2"], DenomConservation { debited: 1950, credited: 1650, burned: 300, commission: 150 });
+
+        // A recipient credited one unit more than was paid for.
+        let (mut balance_changes, summary) = calculate_balance_changes_with_summary(&original_balances, &definitions, &multi_send_tx).unwrap();
+        balance_changes[0].coins[1].amount = Amount::new(1501);
+        let report = ConservationReport::from_changes(&balance_changes, &summary).unwrap();
+        assert!(!report.balanced);
+        assert_eq!(report.denoms["denom2"].credited, 1651);
+    }
+
+    #[test]
+    fn test_apply_fees_only() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+        let fees_only = apply_fees_only(&original_balances, &definitions, &multi_send_tx).unwrap();
+        assert_eq!(
+            fees_only,
+            Balance::from_tuples(vec![
+                ("account1", vec![("denom1", 955), ("denom2", 1700)]),
+                ("account2", vec![("denom1", 470), ("denom2", 1350)]),
+                ("issuer_account", vec![("denom1", 25), ("denom2", 150)]),
+            ])
+        );
+
+        // The full run less the principal moved leaves the same fees.
+        let mut fee_portion = BalanceChanges::default();
+        for balance in calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap() {
+            for coin in &balance.coins {
+                fee_portion.add(&balance.address, &coin.denom, coin.amount.get()).unwrap();
+            }
+        }
+        for (balance, sign) in multi_send_tx.outputs.iter().map(|balance| (balance, -1)).chain(multi_send_tx.inputs.iter().map(|balance| (balance, 1))) {
+            for coin in &balance.coins {
+                fee_portion.add(&balance.address, &coin.denom, sign * coin.amount.get()).unwrap();
+            }
+        }
+        assert_eq!(diff_balances(&original_balances, &fees_only), Ok(fee_portion.into_balances()));
+    }
+
+    #[test]
+    fn test_fee_exempt_senders() {
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 1000)]), ("account2", vec![("denom1", 1000)])]);
+        let definitions = vec![DenomDefinition::with_bps("denom1", "issuer_account", 1000, 500).with_fee_exempt(["account2"])];
+        let multi_send_tx = MultiSendBuilder::new()
+            .input("account1", "denom1", 300)
+            .input("account2", "denom1", 200)
+            .output("account_recipient", "denom1", 500)
+            .build();
+        // Only account1's 300 is charged: 30 burned and 15 commission.
+        assert_eq!(
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx).unwrap(),
+            Balance::from_tuples(vec![("account_recipient", vec![("denom1", 500)]), ("account1", vec![("denom1", -345)]), ("account2", vec![("denom1", -200)]), ("issuer_account", vec![("denom1", 15)])])
+        );
+        assert_eq!(estimate_fees(&definitions, &multi_send_tx).unwrap()["denom1"], (30, 15));
+
+        let definitions = vec![DenomDefinition::with_bps("denom1", "issuer_account", 1000, 500).with_fee_exempt([""])];
+        assert_eq!(calculate_balance_changes(&original_balances, &definitions, &multi_send_tx), Err(BalanceError::InvalidAddress { address: String::new() }));
+    }
+
+    #[test]
+    fn test_collect_diagnostics() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+        let options = CalculationOptions { collect_diagnostics: true, ..CalculationOptions::default() };
+        let (_, summary) = calculate_balance_changes_with_options_and_summary(&original_balances, &definitions, &multi_send_tx, &options).unwrap();
+        let diagnostics = summary.diagnostics.unwrap();
+        assert_eq!(diagnostics.denoms.len(), 2);
+        assert_eq!(diagnostics.denoms["denom1"], DenomDiagnostics { non_issuer_input_sum: 500, output_total: 500, fee_base: 500 });
+        assert_eq!(diagnostics.denoms["denom2"], DenomDiagnostics { non_issuer_input_sum: 1500, output_total: 1500, fee_base: 1500 });
+
+        // Outputs back to the issuer shrink the fee base but not the output total.
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom1", 300).output("account_recipient", "denom1", 200).output("issuer_account", "denom1", 100).build();
+        let (_, summary) = calculate_balance_changes_with_options_and_summary(&original_balances, &definitions, &multi_send_tx, &options).unwrap();
+        assert_eq!(summary.diagnostics.unwrap().denoms["denom1"], DenomDiagnostics { non_issuer_input_sum: 300, output_total: 300, fee_base: 200 });
+        assert_eq!(calculate_balance_changes_with_summary(&original_balances, &definitions, &multi_send_tx).unwrap().1.diagnostics, None);
+    }
+
+    #[test]
+    fn test_missing_definition_reported_for_any_sender() {
+        let definitions = vec![DenomDefinition::with_bps("denom1", "issuer_account", 1000, 500)];
+        let multi_send_tx = MultiSendBuilder::new().input("issuer_account", "denom3", 100).output("account_recipient", "denom3", 100).build();
+        let missing = BalanceError::MissingDefinition { denom: "denom3".to_string() };
+        // Whether the issuer of denom1 holds enough denom3, too little or none.
+        for held in [1000, 50, 0] {
+            let original_balances = Balance::from_tuples(vec![("issuer_account", vec![("denom1", 1000), ("denom3", held)])]);
+            assert_eq!(calculate_balance_changes(&original_balances, &definitions, &multi_send_tx), Err(missing.clone()), "{}", held);
+        }
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 1000)])]);
+        assert_eq!(
+            calculate_balance_changes_collecting(&original_balances, &definitions, &multi_send_tx),
+            Err(vec![BalanceError::UnknownSender { address: "issuer_account".to_string() }, missing])
+        );
+    }
+
+    #[test]
+    fn test_merge_balance_changes() {
+        let (original_balances, definitions, multi_send_tx) = sample();
+        let mut parts: Vec<(String, MultiSend)> = split_by_denom(&multi_send_tx).into_iter().collect();
+        parts.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let results = parts.iter().map(|(_, part)| calculate_balance_changes(&original_balances, &definitions, part).unwrap()).collect();
+        assert_eq!(merge_balance_changes(results), calculate_balance_changes(&original_balances, &definitions, &multi_send_tx));
+
+        // Overlapping results are summed, dropping what nets to zero.
+        let results = vec![
+            Balance::from_tuples(vec![("account1", vec![("denom1", -5)]), ("account2", vec![("denom1", 5)])]),
+            Balance::from_tuples(vec![("account2", vec![("denom1", -5), ("denom2", i128::MAX)]), ("account1", vec![("denom1", 7)])]),
+        ];
+        assert_eq!(merge_balance_changes(results), Ok(Balance::from_tuples(vec![("account1", vec![("denom1", 2)]), ("account2", vec![("denom2", i128::MAX)])])));
+
+        let results = vec![Balance::from_tuples(vec![("account2", vec![("denom2", i128::MAX)])]), Balance::from_tuples(vec![("account2", vec![("denom2", 1)])])];
+        assert_eq!(merge_balance_changes(results), Err(BalanceError::Overflow { denom: "denom2".to_string() }));
+    }
+
+    #[test]
+    fn test_replay() {
+        let initial = Balance::from_tuples(vec![("account1", vec![("denom1", 1000)]), ("account2", vec![("denom1", 500), ("denom2", 10)])]);
+        let definitions = vec![DenomDefinition::with_bps("denom1", "issuer_account", 1000, 500), DenomDefinition::with_bps("denom2", "issuer_account", 0, 0)];
+        let txs = vec![
+            MultiSendBuilder::new().input("account1", "denom1", 200).output("account2", "denom1", 200).build(),
+            MultiSendBuilder::new().input("account2", "denom1", 400).output("account_recipient", "denom1", 400).build(),
+            MultiSendBuilder::new().input("account1", "denom1", 100).output("account_recipient", "denom1", 100).build(),
+        ];
+        // account1 pays 200 + 20 + 10 to account2, which then pays 400 + 40 + 20.
+        assert_eq!(
+            replay(&initial, &definitions, &txs, 2).unwrap(),
+            Balance::from_tuples(vec![
+                ("account1", vec![("denom1", 770)]),
+                ("account2", vec![("denom1", 240), ("denom2", 10)]),
+                ("issuer_account", vec![("denom1", 30)]),
+                ("account_recipient", vec![("denom1", 400)]),
+            ])
+        );
+        assert_eq!(replay(&initial, &definitions, &txs, 0).unwrap(), initial);
+        assert_eq!(replay(&initial, &definitions, &txs, 5).unwrap(), replay(&initial, &definitions, &txs, 3).unwrap());
+        assert_eq!(replay(&initial, &definitions, &txs, 3).unwrap()[0], Balance::new("account1", vec![Coin::new("denom1", 655)]));
+
+        let overdrawn = vec![txs[1].clone(), txs[1].clone()];
+        assert!(matches!(replay(&initial, &definitions, &overdrawn, 2), Err(BalanceError::InsufficientBalance { .. })));
+    }
+}
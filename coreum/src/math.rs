@@ -0,0 +1,76 @@
+#[cfg(not(feature = "std"))]
+use crate::compat::*;
+use crate::{BalanceError, Rate, RoundingMode};
+
+// Amount arithmetic for the calculation. Every checked helper fails with
+// `BalanceError::Overflow` for `denom` when the result doesn't fit in an
+// `i128`, so an overflow is reported the same way wherever it happens. The
+// saturating ones are for figures that are only shown, never used in the
+// calculation.
+
+pub(crate) fn coin_add_checked(lhs: i128, rhs: i128, denom: &str) -> Result<i128, BalanceError> {
+    lhs.checked_add(rhs).ok_or_else(|| overflow(denom))
+}
+
+pub(crate) fn coin_sub_checked(lhs: i128, rhs: i128, denom: &str) -> Result<i128, BalanceError> {
+    lhs.checked_sub(rhs).ok_or_else(|| overflow(denom))
+}
+
+// `amount * rate` rounded to a whole unit as `rounding` says. A negative
+// amount has no fee and is reported as an overflow too, though validation
+// rejects those before any fee is worked out.
+pub(crate) fn coin_mul_rate_checked(amount: i128, rate: Rate, rounding: RoundingMode, denom: &str) -> Result<i128, BalanceError> {
+    rate.mul_rounded(amount, rounding).ok_or_else(|| overflow(denom))
+}
+
+// `lhs - rhs`, clamped to the `i128` range.
+pub(crate) fn coin_sub_saturating(lhs: i128, rhs: i128) -> i128 {
+    lhs.saturating_sub(rhs)
+}
+
+fn overflow(denom: &str) -> BalanceError {
+    BalanceError::Overflow { denom: denom.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overflowed() -> Result<i128, BalanceError> {
+        Err(BalanceError::Overflow { denom: "denom1".to_string() })
+    }
+
+    #[test]
+    fn test_coin_add_checked() {
+        assert_eq!(coin_add_checked(i128::MAX - 1, 1, "denom1"), Ok(i128::MAX));
+        assert_eq!(coin_add_checked(i128::MAX, 1, "denom1"), overflowed());
+        assert_eq!(coin_add_checked(i128::MIN, -1, "denom1"), overflowed());
+        assert_eq!(coin_add_checked(i128::MIN, i128::MAX, "denom1"), Ok(-1));
+    }
+
+    #[test]
+    fn test_coin_sub_checked() {
+        assert_eq!(coin_sub_checked(0, i128::MAX, "denom1"), Ok(-i128::MAX));
+        assert_eq!(coin_sub_checked(-2, i128::MAX, "denom1"), overflowed());
+        assert_eq!(coin_sub_checked(i128::MIN + 1, 1, "denom1"), Ok(i128::MIN));
+        assert_eq!(coin_sub_checked(i128::MAX, -1, "denom1"), overflowed());
+    }
+
+    #[test]
+    fn test_coin_sub_saturating() {
+        assert_eq!(coin_sub_saturating(0, i128::MAX), -i128::MAX);
+        assert_eq!(coin_sub_saturating(-2, i128::MAX), i128::MIN);
+        assert_eq!(coin_sub_saturating(i128::MAX, -1), i128::MAX);
+        assert_eq!(coin_sub_saturating(i128::MAX, i128::MIN), i128::MAX);
+    }
+
+    #[test]
+    fn test_coin_mul_rate_checked() {
+        assert_eq!(coin_mul_rate_checked(i128::MAX, Rate::ONE, RoundingMode::Ceil, "denom1"), Ok(i128::MAX));
+        assert_eq!(coin_mul_rate_checked(i128::MAX, Rate::from_bps(5000), RoundingMode::Floor, "denom1"), Ok(i128::MAX / 2));
+        assert_eq!(coin_mul_rate_checked(i128::MAX, Rate::from_bps(5000), RoundingMode::Ceil, "denom1"), Ok(i128::MAX / 2 + 1));
+        assert_eq!(coin_mul_rate_checked(i128::MAX, Rate::from_bps(10_001), RoundingMode::Floor, "denom1"), overflowed());
+        assert_eq!(coin_mul_rate_checked(-1, Rate::from_bps(5000), RoundingMode::Floor, "denom1"), overflowed());
+        assert_eq!(coin_mul_rate_checked(0, Rate::ONE, RoundingMode::Ceil, "denom1"), Ok(0));
+    }
+}
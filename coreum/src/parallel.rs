@@ -0,0 +1,123 @@
+use rayon::prelude::*;
+
+use crate::compat::{Map, Set};
+use crate::{calculate_balance_changes, check_shape, merge_balance_changes, split_by_denom, Balance, BalanceError, CalculationOptions, Coin, DenomDefinition, Ledger, MultiSend};
+
+/// Same as [`calculate_balance_changes`], but works out each denom of the
+/// transaction on its own rayon task. Burn and commission are independent per
+/// denom, so the changes are the same; only their order differs: addresses
+/// in the order they first appear going through the denoms alphabetically,
+/// with coins in denom order. That order doesn't depend on thread scheduling.
+///
+/// Checks that span denoms (the shape of every entry, the original balances
+/// and the definitions) run up front, and the rest per denom. When a
+/// transaction has several problems, the error returned is the first one for the
+/// alphabetically smallest failing denom, which can differ from the one
+/// [`calculate_balance_changes`] returns.
+pub fn calculate_balance_changes_parallel(
+    original_balances: &[Balance],
+    definitions: &[DenomDefinition],
+    multi_send_tx: &MultiSend,
+) -> Result<Vec<Balance>, BalanceError> {
+    // In the order the serial checks report them. Entries without coins
+    // vanish from the split, so they're only checked here.
+    let mut errors = Vec::new();
+    check_shape(multi_send_tx, &CalculationOptions::default(), &mut errors);
+    let (ledger, ledger_errors) = Ledger::collect(original_balances);
+    errors.extend(ledger_errors);
+    for definition in definitions {
+        if let Err(error) = definition.validate() {
+            errors.push(error);
+        }
+    }
+    if let Some(error) = errors.into_iter().next() {
+        return Err(error);
+    }
+
+    // One pass over the transaction and definitions builds every part, which
+    // only gets the balances and definitions of its own denom. Parts come in
+    // denom order.
+    let mut definitions_by_denom: Map<&str, Vec<DenomDefinition>> = Map::new();
+    for definition in definitions {
+        definitions_by_denom.entry(definition.denom.as_str()).or_default().push(definition.clone());
+    }
+    let parts: Vec<(Vec<Balance>, Vec<DenomDefinition>, MultiSend)> = split_by_denom(multi_send_tx)
+        .into_iter()
+        .map(|(denom, part)| {
+            let definitions = definitions_by_denom.remove(denom.as_str()).unwrap_or_default();
+            let issuers = definitions.iter().map(|definition| definition.issuer.as_str());
+            let addresses = part.inputs.iter().chain(&part.outputs).map(|balance| balance.address.as_str()).chain(issuers);
+            let mut seen = Set::new();
+            let original_balances: Vec<Balance> = addresses
+                .filter(|&address| ledger.contains(address) && seen.insert(address))
+                .map(|address| Balance::new(address, vec![Coin::new(&denom, ledger.balance_of(address, &denom))]))
+                .collect();
+            (original_balances, definitions, part)
+        })
+        .collect();
+
+    let results: Vec<Result<Vec<Balance>, BalanceError>> =
+        parts.into_par_iter().map(|(original_balances, definitions, part)| calculate_balance_changes(&original_balances, &definitions, &part)).collect();
+    // Every part has a denom to itself, so merging never sums anything.
+    merge_balance_changes(results.into_iter().collect::<Result<_, _>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{calculate_and_verify, MultiSendBuilder};
+
+    #[test]
+    fn test_parallel_matches_serial() {
+        let denom = |d: usize| format!("denom{}", d);
+        let original_balances: Vec<Balance> = (0..5).map(|a| Balance::new(format!("account{}", a), (0..40).map(|d| Coin::new(denom(d), 1_000_000)).collect())).collect();
+        let definitions: Vec<DenomDefinition> = (0..40).map(|d| DenomDefinition::with_bps(denom(d), format!("issuer{}", d % 3), 13 * d as u32, 7 * d as u32)).collect();
+        let mut builder = MultiSendBuilder::new();
+        for a in 0..5 {
+            for d in (0..40).filter(|d| (a + d) % 3 != 0) {
+                builder = builder.input(format!("account{}", a), denom(d), 100 + (a * d) as i128).output(format!("recipient{}", a), denom(d), 100 + (a * d) as i128);
+            }
+        }
+        let multi_send_tx = builder.build();
+
+        let parallel = calculate_balance_changes_parallel(&original_balances, &definitions, &multi_send_tx).unwrap();
+        assert_eq!(calculate_and_verify(&original_balances, &definitions, &multi_send_tx, &parallel), Ok(()));
+        assert_eq!(calculate_balance_changes_parallel(&original_balances, &definitions, &multi_send_tx).unwrap(), parallel);
+        assert!(parallel.iter().all(|balance| balance.coins.windows(2).all(|pair| pair[0].denom < pair[1].denom)));
+    }
+
+    #[test]
+    fn test_parallel_checks_span_denoms() {
+        let original_balances = Balance::from_tuples(vec![("account1", vec![("denom1", 1000), ("denom2", 1000)])]);
+        let definitions = vec![DenomDefinition::with_bps("denom1", "issuer_account", 1000, 500), DenomDefinition::with_bps("denom2", "issuer_account", 1000, 500)];
+        // Split apart, each denom alone would list account1 once.
+        let multi_send_tx = MultiSend::new(
+            Balance::from_tuples(vec![("account1", vec![("denom1", 10)]), ("account1", vec![("denom2", 10)])]),
+            Balance::from_tuples(vec![("account_recipient", vec![("denom1", 10), ("denom2", 10)])]),
+        );
+        assert_eq!(
+            calculate_balance_changes_parallel(&original_balances, &definitions, &multi_send_tx),
+            Err(BalanceError::DuplicateAddress { address: "account1".to_string() })
+        );
+
+        // Entries without coins aren't part of any denom, but are still checked.
+        let multi_send_tx = MultiSend::new(vec![Balance::new("a b", vec![])], vec![]);
+        assert_eq!(calculate_balance_changes_parallel(&original_balances, &definitions, &multi_send_tx), Err(BalanceError::InvalidAddress { address: "a b".to_string() }));
+        let mut outputs = Balance::from_tuples(vec![("account_recipient", vec![("denom1", 10)])]);
+        outputs.push(Balance::new("", vec![]));
+        let multi_send_tx = MultiSend::new(Balance::from_tuples(vec![("account1", vec![("denom1", 10)])]), outputs);
+        assert_eq!(
+            calculate_balance_changes_parallel(&original_balances, &definitions, &multi_send_tx),
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx)
+        );
+        assert_eq!(calculate_balance_changes_parallel(&original_balances, &definitions, &multi_send_tx), Err(BalanceError::InvalidAddress { address: String::new() }));
+
+        // A known sender holding none of a denom is short of it, not unknown.
+        let multi_send_tx = MultiSendBuilder::new().input("account1", "denom3", 10).output("account_recipient", "denom3", 10).build();
+        let definitions = vec![DenomDefinition::with_bps("denom3", "issuer_account", 0, 0)];
+        assert_eq!(
+            calculate_balance_changes_parallel(&original_balances, &definitions, &multi_send_tx),
+            calculate_balance_changes(&original_balances, &definitions, &multi_send_tx)
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use core::cmp::Ordering;
+use core::fmt;
+use core::str::FromStr;
+
+#[cfg(not(feature = "std"))]
+use crate::compat::*;
+
+const DECIMALS: u32 = 18;
+const ONE_ATOMICS: i128 = 10i128.pow(DECIMALS);
+
+/// A fixed-point decimal rate with 18 fractional digits, the same precision as
+/// the Cosmos SDK `Dec` type. It is stored as an integer count of 10^-18 units
+/// so burn and commission math never goes through floating point.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rate(i128);
+
+impl Rate {
+    pub const DECIMALS: u32 = DECIMALS;
+    pub const ZERO: Rate = Rate(0);
+    pub const ONE: Rate = Rate(ONE_ATOMICS);
+
+    /// Builds a rate from its raw count of 10^-18 units.
+    pub const fn from_atomics(atomics: i128) -> Self {
+        Rate(atomics)
+    }
+
+    /// Builds a rate from basis points, so `Rate::from_bps(500)` is 5%.
+    pub const fn from_bps(bps: u32) -> Self {
+        Rate(bps as i128 * (ONE_ATOMICS / 10_000))
+    }
+
+    pub const fn atomics(self) -> i128 {
+        self.0
+    }
+
+    pub const fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `amount * self` rounded to a whole unit as `rounding` says, or
+    /// `None` if either is negative or the result doesn't fit in an `i128`.
+    pub(crate) fn mul_rounded(self, amount: i128, rounding: RoundingMode) -> Option<i128> {
+        let (quotient, remainder) = mul_div_rem(amount, self.0, ONE_ATOMICS)?;
+        let round_up = match rounding {
+            RoundingMode::Ceil => remainder != 0,
+            RoundingMode::Floor => false,
+            RoundingMode::HalfUp => 2 * remainder >= ONE_ATOMICS,
+            RoundingMode::HalfEven => match (2 * remainder).cmp(&ONE_ATOMICS) {
+                Ordering::Less => false,
+                Ordering::Equal => quotient % 2 == 1,
+                Ordering::Greater => true,
+            },
+        };
+        if round_up {
+            quotient.checked_add(1)
+        } else {
+            Some(quotient)
+        }
+    }
+}
+
+/// How a fee total that isn't a whole number of units is rounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum RoundingMode {
+    /// Round up, so fees are never undercharged.
+    #[default]
+    Ceil,
+    /// Round down, so fees are never overcharged.
+    Floor,
+    /// Round to the nearest unit, and up on an exact half.
+    HalfUp,
+    /// Round to the nearest unit, and to the even one on an exact half
+    /// (banker's rounding), so rounding is unbiased over many transfers.
+    HalfEven,
+}
+
+/// Returns `(a * b / c, a * b % c)` with the product computed at full width, or
+/// `None` if an operand is negative, `c` is zero, or the quotient doesn't fit
+/// in an `i128`.
+pub(crate) fn mul_div_rem(a: i128, b: i128, c: i128) -> Option<(i128, i128)> {
+    if a < 0 || b < 0 || c <= 0 {
+        return None;
+    }
+    let (quotient, remainder) = Wide::from_u128(a as u128).mul(b as u128).div_rem(c as u128);
+    let quotient = quotient.to_u128().and_then(|value| i128::try_from(value).ok())?;
+    Some((quotient, remainder as i128))
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let atomics = self.0.unsigned_abs();
+        let whole = atomics / ONE_ATOMICS as u128;
+        let fraction = atomics % ONE_ATOMICS as u128;
+        if fraction == 0 {
+            write!(f, "{}{}", sign, whole)
+        } else {
+            let digits = format!("{:018}", fraction);
+            write!(f, "{}{}.{}", sign, whole, digits.trim_end_matches('0'))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRateError {
+    input: String,
+}
+
+impl fmt::Display for ParseRateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} is not a valid decimal rate", self.input)
+    }
+}
+
+impl core::error::Error for ParseRateError {}
+
+impl FromStr for Rate {
+    type Err = ParseRateError;
+
+    /// Parses a plain decimal such as `"0.05"` or `"-1.5"` with at most 18
+    /// fractional digits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseRateError { input: s.to_string() };
+        let (negative, unsigned) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() || !is_digits(whole) || !is_digits(fraction) || fraction.len() > DECIMALS as usize {
+            return Err(error());
+        }
+        if unsigned.ends_with('.') {
+            return Err(error());
+        }
+        let whole: i128 = whole.parse().map_err(|_| error())?;
+        let fraction: i128 = if fraction.is_empty() {
+            0
+        } else {
+            fraction.parse::<i128>().map_err(|_| error())? * 10i128.pow(DECIMALS - fraction.len() as u32)
+        };
+        let atomics = whole
+            .checked_mul(ONE_ATOMICS)
+            .and_then(|value| value.checked_add(fraction))
+            .ok_or_else(error)?;
+        Ok(Rate(if negative { -atomics } else { atomics }))
+    }
+}
+
+impl TryFrom<f64> for Rate {
+    type Error = ParseRateError;
+
+    /// Converts through the shortest decimal that reads back as `value`, so
+    /// `0.05` becomes exactly 0.05 rather than the nearest binary fraction,
+    /// 0.05000000000000000277. NaN and infinities are rejected, and so is
+    /// anything needing more than 18 fractional digits. Negative values
+    /// convert, and fail [`DenomDefinition`](crate::DenomDefinition)
+    /// validation like any other rate outside [0, 1].
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if !value.is_finite() {
+            return Err(ParseRateError { input: value.to_string() });
+        }
+        // `f64`'s `Display` never uses an exponent, which `from_str` rejects.
+        value.to_string().parse()
+    }
+}
+
+/// Serialized as a decimal string, e.g. `"0.05"`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Rate {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Rate {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let rate = String::deserialize(deserializer)?;
+        rate.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+const LIMBS: usize = 4;
+
+/// Unsigned 256-bit integer, wide enough to hold the product of two `i128`
+/// magnitudes without overflowing.
+#[derive(Debug, Clone, Copy)]
+struct Wide([u64; LIMBS]);
+
+impl Wide {
+    fn from_u128(value: u128) -> Self {
+        let mut limbs = [0; LIMBS];
+        limbs[0] = value as u64;
+        limbs[1] = (value >> 64) as u64;
+        Wide(limbs)
+    }
+
+    fn mul(self, rhs: u128) -> Self {
+        let rhs = [rhs as u64, (rhs >> 64) as u64];
+        let mut limbs = [0u64; LIMBS];
+        for (i, &lhs) in self.0.iter().enumerate() {
+            let mut carry = 0u128;
+            for (j, &rhs) in rhs.iter().enumerate() {
+                if i + j >= LIMBS {
+                    break;
+                }
+                let value = lhs as u128 * rhs as u128 + limbs[i + j] as u128 + carry;
+                limbs[i + j] = value as u64;
+                carry = value >> 64;
+            }
+            let mut k = i + rhs.len();
+            while carry != 0 && k < LIMBS {
+                let value = limbs[k] as u128 + carry;
+                limbs[k] = value as u64;
+                carry = value >> 64;
+                k += 1;
+            }
+        }
+        Wide(limbs)
+    }
+
+    /// Long division by a divisor no larger than `i128::MAX`, so the running
+    /// remainder can be shifted left without overflowing.
+    fn div_rem(self, divisor: u128) -> (Self, u128) {
+        debug_assert!(divisor != 0 && divisor <= i128::MAX as u128);
+        let mut quotient = [0u64; LIMBS];
+        let mut remainder = 0u128;
+        for bit in (0..LIMBS * 64).rev() {
+            remainder = (remainder << 1) | ((self.0[bit / 64] >> (bit % 64)) & 1) as u128;
+            if remainder >= divisor {
+                remainder -= divisor;
+                quotient[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        (Wide(quotient), remainder)
+    }
+
+    fn to_u128(self) -> Option<u128> {
+        if self.0[2..].iter().any(|&limb| limb != 0) {
+            return None;
+        }
+        Some(self.0[0] as u128 | (self.0[1] as u128) << 64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        let rate: Rate = "0.05".parse().unwrap();
+        assert_eq!(rate, Rate::from_bps(500));
+        assert_eq!(rate.to_string(), "0.05");
+        assert_eq!("1".parse::<Rate>().unwrap(), Rate::ONE);
+        assert_eq!("-1.5".parse::<Rate>().unwrap().to_string(), "-1.5");
+        assert_eq!("0.000000000000000001".parse::<Rate>().unwrap(), Rate::from_atomics(1));
+        assert!("".parse::<Rate>().is_err());
+        assert!("0.".parse::<Rate>().is_err());
+        assert!(".5".parse::<Rate>().is_err());
+        assert!("+0.5".parse::<Rate>().is_err());
+        assert!("1e-3".parse::<Rate>().is_err());
+        assert!("0.0000000000000000001".parse::<Rate>().is_err());
+    }
+
+    #[test]
+    fn test_mul_ceil_is_exact() {
+        // 30 * 0.1 is 3.0000000000000004 in f64, which ceils to 4
+        assert_eq!(Rate::from_bps(1000).mul_rounded(30, RoundingMode::Ceil), Some(3));
+        assert_eq!(Rate::from_bps(500).mul_rounded(30, RoundingMode::Ceil), Some(2));
+        assert_eq!(Rate::ONE.mul_rounded(i128::MAX, RoundingMode::Ceil), Some(i128::MAX));
+        assert_eq!(Rate::from_atomics(ONE_ATOMICS + 1).mul_rounded(i128::MAX, RoundingMode::Ceil), None);
+        assert_eq!(Rate::from_bps(500).mul_rounded(-1, RoundingMode::Ceil), None);
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        let rate = Rate::from_bps(1000);
+        for (amount, ceil, floor, half_up, half_even) in [(30, 3, 3, 3, 3), (31, 4, 3, 3, 3), (25, 3, 2, 3, 2), (35, 4, 3, 4, 4), (36, 4, 3, 4, 4)] {
+            assert_eq!(rate.mul_rounded(amount, RoundingMode::Ceil), Some(ceil));
+            assert_eq!(rate.mul_rounded(amount, RoundingMode::Floor), Some(floor));
+            assert_eq!(rate.mul_rounded(amount, RoundingMode::HalfUp), Some(half_up));
+            assert_eq!(rate.mul_rounded(amount, RoundingMode::HalfEven), Some(half_even));
+        }
+    }
+
+    #[test]
+    fn test_mul_div_rem_uses_full_width() {
+        assert_eq!(mul_div_rem(i128::MAX, i128::MAX, i128::MAX), Some((i128::MAX, 0)));
+        assert_eq!(mul_div_rem(i128::MAX, 3, 4), Some((i128::MAX / 4 * 3 + 2, 1)));
+        assert_eq!(mul_div_rem(i128::MAX, 2, 1), None);
+        assert_eq!(mul_div_rem(1, 1, 0), None);
+    }
+
+    #[test]
+    fn test_mul_rounded_is_exact_above_f64_precision() {
+        // 2^53 + 1, the smallest amount an f64 can't hold: it reads as 2^53,
+        // so half of it would ceil to 2^52 instead of 2^52 + 1.
+        let base: i128 = (1 << 53) + 1;
+        let float = (base as f64 * 0.5).ceil() as i128;
+        assert_eq!(float, 1 << 52);
+        assert_eq!(Rate::from_bps(5000).mul_rounded(base, RoundingMode::Ceil), Some((1 << 52) + 1));
+        assert_eq!(Rate::ONE.mul_rounded(base, RoundingMode::Floor), Some(base));
+    }
+
+    #[test]
+    fn test_try_from_f64() {
+        assert_eq!(Rate::try_from(0.05), Ok(Rate::from_bps(500)));
+        assert_eq!(Rate::try_from(1.0), Ok(Rate::ONE));
+        assert_eq!(Rate::try_from(1e-18), Ok(Rate::from_atomics(1)));
+        assert_eq!(Rate::try_from(-0.1), Ok(Rate::from_atomics(-Rate::from_bps(1000).atomics())));
+        assert_eq!(Rate::try_from(f64::NAN), Err(ParseRateError { input: "NaN".to_string() }));
+        assert!(Rate::try_from(f64::INFINITY).is_err());
+        assert!(Rate::try_from(1e-19).is_err());
+
+        let definition = crate::DenomDefinition::new("denom1", "issuer_account", Rate::try_from(-0.1).unwrap(), Rate::ZERO);
+        assert!(matches!(definition.validate(), Err(crate::BalanceError::InvalidRate { .. })));
+    }
+}
@@ -0,0 +1,59 @@
+#![cfg(feature = "cli")]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use serde_json::{json, Value};
+
+const BIN: &str = env!("CARGO_BIN_EXE_coreum");
+
+#[test]
+fn test_cli_reads_file() {
+    let output = Command::new(BIN)
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/sample_transfer.json"))
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let balance_changes: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        balance_changes,
+        json!([
+            {"address": "account_recipient", "coins": [{"denom": "denom1", "amount": "500"}, {"denom": "denom2", "amount": "1500"}]},
+            {"address": "account1", "coins": [{"denom": "denom1", "amount": "-345"}, {"denom": "denom2", "amount": "-1300"}]},
+            {"address": "account2", "coins": [{"denom": "denom1", "amount": "-230"}, {"denom": "denom2", "amount": "-650"}]},
+            {"address": "issuer_account", "coins": [{"denom": "denom1", "amount": "25"}, {"denom": "denom2", "amount": "150"}]}
+        ])
+    );
+}
+
+#[test]
+fn test_cli_reports_errors_from_stdin() {
+    let mut child = Command::new(BIN)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(
+            br#"{
+                "original_balances": [{"address": "account1", "coins": [{"denom": "denom1", "amount": "100"}]}],
+                "definitions": [],
+                "multi_send_tx": {
+                    "inputs": [{"address": "account1", "coins": [{"denom": "denom1", "amount": "10"}]}],
+                    "outputs": [{"address": "account2", "coins": [{"denom": "denom1", "amount": "10"}]}]
+                }
+            }"#,
+        )
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let error: Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(error, json!({"error": "Denomination denom1 does not have a definition"}));
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "json")]
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use coreum::calculate_balance_changes_json;
+
+// Each `<name>.json` in `tests/fixtures` holds the same document as the
+// command-line binary reads, next to a `<name>.expected.json` with what
+// `calculate_balance_changes_json` returns for it: either the balance changes
+// or `{"error": "..."}` for a rejected transfer. Adding a case only takes the
+// two files.
+#[test]
+fn test_golden_files() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut names: Vec<String> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter_map(|name| name.strip_suffix(".json").filter(|name| !name.ends_with(".expected")).map(str::to_string))
+        .collect();
+    names.sort();
+    assert!(!names.is_empty(), "no fixtures in {}", dir.display());
+
+    for name in names {
+        let read = |file: String| fs::read_to_string(dir.join(&file)).unwrap_or_else(|err| panic!("{}: {}", file, err));
+        let expected: Value = serde_json::from_str(&read(format!("{}.expected.json", name))).unwrap_or_else(|err| panic!("{}: {}", name, err));
+        let actual: Value = serde_json::from_str(&calculate_balance_changes_json(&read(format!("{}.json", name)))).unwrap();
+        assert_eq!(actual, expected, "{}", name);
+    }
+}
@@ -0,0 +1,86 @@
+use proptest::prelude::*;
+
+use coreum::{apply_multi_send, calculate_balance_changes_with_summary, Amount, Balance, Coin, DenomDefinition, MultiSend, MultiSendBuilder, Rate};
+
+const ACCOUNTS: usize = 4;
+const DENOMS: usize = 3;
+
+// Indices past the accounts stand for an issuer and a recipient that hold
+// nothing before the transfer.
+fn address(index: usize) -> String {
+    match index {
+        index if index < ACCOUNTS => format!("account{}", index),
+        index if index == ACCOUNTS => "issuer_account".to_string(),
+        _ => "account_recipient".to_string(),
+    }
+}
+
+fn denom(index: usize) -> String {
+    format!("denom{}", index)
+}
+
+// Biased towards single units, where rounding matters most.
+fn amount() -> impl Strategy<Value = i128> {
+    prop_oneof![0..10i128, 0..1_000_000i128, 0..10i128.pow(24)]
+}
+
+// Biased towards 0 and 1 and the atomics just inside them.
+fn rate() -> impl Strategy<Value = Rate> {
+    let one = Rate::ONE.atomics();
+    prop_oneof![Just(0), Just(one), 0..1_000i128, one - 1_000..=one, 0..=one].prop_map(Rate::from_atomics)
+}
+
+prop_compose! {
+    // Every account holds every denom and sends at most a third of it, which
+    // leaves room for burn and commission of up to 100% each plus rounding.
+    fn transfer()(
+        holdings in prop::collection::vec(amount(), ACCOUNTS * DENOMS),
+        sent_percent in prop::collection::vec(0..=100i128, ACCOUNTS * DENOMS),
+        rates in prop::collection::vec((rate(), rate()), DENOMS),
+        issuers in prop::collection::vec(0..=ACCOUNTS, DENOMS),
+        recipients in prop::collection::vec((0..ACCOUNTS + 2, 0..ACCOUNTS + 2, 0..=100i128), DENOMS),
+    ) -> (Vec<Balance>, Vec<DenomDefinition>, MultiSend) {
+        let original_balances = (0..ACCOUNTS)
+            .map(|a| Balance::new(address(a), (0..DENOMS).map(|d| Coin::new(denom(d), holdings[a * DENOMS + d])).collect()))
+            .collect();
+        let definitions = (0..DENOMS).map(|d| DenomDefinition::new(denom(d), address(issuers[d]), rates[d].0, rates[d].1)).collect();
+
+        let mut builder = MultiSendBuilder::new();
+        let mut sent = [0i128; DENOMS];
+        for a in 0..ACCOUNTS {
+            for d in 0..DENOMS {
+                let amount = (holdings[a * DENOMS + d] - 2).max(0) / 3 * sent_percent[a * DENOMS + d] / 100;
+                builder = builder.input(address(a), denom(d), amount);
+                sent[d] += amount;
+            }
+        }
+        for (d, &(first, second, percent)) in recipients.iter().enumerate() {
+            if first == second {
+                builder = builder.output(address(first), denom(d), sent[d]);
+            } else {
+                let part = sent[d] * percent / 100;
+                builder = builder.output(address(first), denom(d), part).output(address(second), denom(d), sent[d] - part);
+            }
+        }
+        (original_balances, definitions, builder.build())
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_transfers_conserve_value((original_balances, definitions, multi_send_tx) in transfer()) {
+        let (balance_changes, summary) = calculate_balance_changes_with_summary(&original_balances, &definitions, &multi_send_tx).unwrap();
+
+        // Outputs and commission are credited out of what senders are debited,
+        // so the only value leaving circulation is the burn.
+        for d in 0..DENOMS {
+            let net: i128 = balance_changes.iter().flat_map(Balance::coins).filter(|coin| coin.denom() == denom(d)).map(|coin| coin.amount().get()).sum();
+            prop_assert_eq!(net, -summary.burned.get(&denom(d)).copied().unwrap_or(0));
+        }
+
+        let final_balances = apply_multi_send(&original_balances, &definitions, &multi_send_tx).unwrap();
+        for coin in final_balances.iter().flat_map(Balance::coins) {
+            prop_assert!(coin.amount() >= Amount::ZERO, "{:?}", final_balances);
+        }
+    }
+}